@@ -11,9 +11,14 @@
                       },
 // 视野参数结构体
 struct VisionParams {
-    position: vec2<f32>,  // 世界空间位置
+    tint: vec4<f32>,      // 预乘的线性色调
+    position: vec2<f32>,  // 迷雾空间位置
     range: f32,           // 视野范围
     falloff: f32,         // 边缘衰减
+    rotation: f32,        // 绕 Z 轴的旋转（弧度）
+    stamp_layer: i32,     // 印章图集层，-1 表示圆形视野
+    strength: f32,        // 可见性强度，临时揭示淡出时小于 1
+    eye_height: f32,      // 视点高度，看不到更高的地形
 };
 
 // 视野参数数组
@@ -24,9 +29,57 @@ struct VisionArray {
 
 // 使用group(1)避免与Bevy内置绑定冲突
 @group(1) @binding(0) var<storage, read> visions: VisionArray;
-@group(1) @binding(1) var output_texture: texture_storage_2d<r32float, write>;
+@group(1) @binding(1) var output_texture: texture_storage_2d<rgba16float, write>;
+@group(1) @binding(2) var stamp_textures: texture_2d_array<f32>;
+@group(1) @binding(3) var stamp_sampler: sampler;
 @group(0) @binding(0) var<uniform> view: View;
 
+// 世界到迷雾空间的变换，视野范围在迷雾空间中计算
+// World to fog space transform; vision ranges are measured in fog space
+struct FogSpace {
+    world_to_fog: mat2x2<f32>,
+    height_origin: vec2<f32>,
+    height_cell_size: f32,
+    use_height_map: u32,
+};
+@group(1) @binding(4) var<uniform> fog_space: FogSpace;
+
+// 地形高度图，第一行位于 y 最大处
+// Terrain height map, with the first row at the greatest y
+@group(1) @binding(5) var height_map: texture_2d<f32>;
+
+// 迷雾空间位置的地形高度，高度图外为 0
+// Terrain height at a fog space position, 0 outside the height map
+fn terrain_height(fog_position: vec2<f32>) -> f32 {
+    if (fog_space.use_height_map == 0u) {
+        return 0.0;
+    }
+    let cell = vec2<i32>(floor((fog_position - fog_space.height_origin) / fog_space.height_cell_size));
+    let dims = vec2<i32>(textureDimensions(height_map));
+    if (any(cell < vec2<i32>(0)) || any(cell >= dims)) {
+        return 0.0;
+    }
+    return textureLoad(height_map, vec2<i32>(cell.x, dims.y - 1 - cell.y), 0).r;
+}
+
+// 在印章中采样视野：印章以提供者为中心，边长为两倍视野范围，并随其旋转
+// Sample the vision from a stamp centered on the provider, twice the range wide and rotating with it
+fn stamp_visibility(vision: VisionParams, fog_position: vec2<f32>) -> f32 {
+    let offset = fog_position - vision.position;
+    let c = cos(vision.rotation);
+    let s = sin(vision.rotation);
+    // 逆旋转到印章局部空间
+    // Rotate back into stamp local space
+    let local = vec2<f32>(c * offset.x + s * offset.y, -s * offset.x + c * offset.y) / (2.0 * vision.range);
+    // 图像第一行位于上方
+    // The first image row is at the top
+    let uv = vec2<f32>(0.5 + local.x, 0.5 - local.y);
+    if (any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0))) {
+        return 0.0;
+    }
+    return textureSampleLevel(stamp_textures, stamp_sampler, uv, vision.stamp_layer, 0.0).r;
+}
+
 // 计算着色器入口点
 @compute @workgroup_size(8, 8)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
@@ -36,32 +89,55 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     // 创建一个完整的vec3<f32>作为NDC坐标 (x,y来自uv转换，z设为0.0)
     let ndc_pos = vec3<f32>(ndc, 0.0);
     let world_position = position_ndc_to_world(ndc_pos);
+    let fog_position = fog_space.world_to_fog * world_position.xy;
+    let height = terrain_height(fog_position);
     
     // 计算该像素的可见性 
     // Calculate the visibility of this pixel
     var combined_visibility = 0.0;
+    // 按可见性加权的色调之和
+    // Sum of tints weighted by visibility
+    var tint_sum = vec3<f32>(0.0);
+    var tint_weight = 0.0;
     
     // 遍历所有视野提供者
     // Iterate through all vision providers
     for (var i = 0u; i < arrayLength(&visions.data); i++) {
        let vision = visions.data[i];
-       let dist = distance(world_position.xy, vision.position);
-       if (dist < vision.range) {
-           // 使用平滑函数计算当前视野的可见性值
-           // Calculate the visibility value for the current vision using a smooth function
-           let visibility = 1.0 - smoothstep(vision.range * vision.falloff, vision.range, dist);
-           
-           // 使用累加混合方法替代max函数，从而避免生成明显的边界线
-           // Use an accumulative blending method instead of max function to avoid creating visible boundary lines
-           combined_visibility = combined_visibility + visibility * (1.0 - combined_visibility);
+       var visibility = 0.0;
+       if (vision.stamp_layer >= 0) {
+           visibility = stamp_visibility(vision, fog_position);
+       } else {
+           let dist = distance(fog_position, vision.position);
+           if (dist < vision.range) {
+               // 使用平滑函数计算当前视野的可见性值
+               // Calculate the visibility value for the current vision using a smooth function
+               visibility = 1.0 - smoothstep(vision.range * vision.falloff, vision.range, dist);
+           }
        }
+       // 临时揭示随时间淡出
+       // Temporary reveals fade out over time
+       visibility = visibility * vision.strength;
+       // 提供者能看到低处，但看不到高于视点的台地
+       // Providers see down into lower terrain but not up onto ledges above their eye
+       visibility = visibility * step(height, vision.eye_height);
+
+       // 使用累加混合方法替代max函数，从而避免生成明显的边界线
+       // Use an accumulative blending method instead of max function to avoid creating visible boundary lines
+       combined_visibility = combined_visibility + visibility * (1.0 - combined_visibility);
+       tint_sum = tint_sum + vision.tint.rgb * visibility;
+       tint_weight = tint_weight + visibility;
     }
     
     // 确保可见性值在有效范围内
     // Ensure visibility value is in valid range
     var final_visibility = clamp(combined_visibility, 0.0, 1.0);
+
+    // 重叠区域取色调的加权平均，并随可见性淡出
+    // Overlapping areas use the weighted average tint, fading out with visibility
+    let tint = tint_sum / max(tint_weight, 1e-4) * final_visibility;
     
     // 存储结果到纹理
     // Store the result to the texture
-    textureStore(output_texture, global_id.xy, vec4<f32>(final_visibility));
+    textureStore(output_texture, global_id.xy, vec4<f32>(tint, final_visibility));
 }
@@ -1,7 +1,12 @@
 use bevy::{
     color::palettes::css::{GOLD, RED},
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    asset::RenderAssetUsages,
     prelude::*,
+    render::{
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        view::RenderLayers,
+    },
 };
 use zing_fog2d::prelude::*;
 
@@ -23,6 +28,18 @@ fn main() {
             chunk_size: 256.0,
             view_range: 5,
             debug_draw: true,
+            // 六边形地图可使用 ChunkLayout::HexPointy 或 ChunkLayout::HexFlat
+            // Hex maps can use ChunkLayout::HexPointy or ChunkLayout::HexFlat
+            layout: ChunkLayout::Square,
+            // 等距地图可使用 FogOfWarConfig::isometric(Vec2::new(64.0, 32.0))
+            // Isometric maps can use FogOfWarConfig::isometric(Vec2::new(64.0, 32.0))
+            world_to_fog: Mat2::IDENTITY,
+            // 本地玩家所在的队伍
+            // Team of the local player
+            viewer_team: FogTeam(0),
+            // 迷雾显示的视野层
+            // Vision layers the fog shows
+            vision_layers: VisionLayers::GROUND,
         })
         .add_plugins(ZingFogPlugins)
         .add_systems(Startup, (setup, setup_ui))
@@ -34,6 +51,9 @@ fn main() {
                 update_fps_text,
                 update_fog_settings_text,
                 text_color_animation,
+                save_fog_snapshot,
+                rotate_searchlight,
+                fog_commands,
             ),
         )
         .run();
@@ -42,6 +62,11 @@ fn main() {
 #[derive(Component)]
 struct MainCamera;
 
+/// 探照灯组件标记
+/// Searchlight component marker
+#[derive(Component)]
+struct Searchlight;
+
 /// 帧率文本组件标记
 /// FPS text component marker
 #[derive(Component)]
@@ -57,7 +82,11 @@ struct FogSettingsText;
 #[derive(Component)]
 struct ColorAnimatedText;
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+) {
     // 加载噪声纹理
     // Load noise texture
     let noise_texture = asset_server.load("textures/noise.png");
@@ -82,10 +111,57 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             // 噪声速度默认为0.0（静态）
             // Default noise speed is 0.0 (static)
             noise_speed: 0.0,
+            // 默认全屏叠加，按 M 切换到世界空间图层
+            // Full-screen overlay by default, press M to switch to the world-space layer
+            render_mode: FogRenderMode::Overlay,
+            // 只有默认层受迷雾覆盖
+            // Only the default layer is fogged
+            fogged_layers: RenderLayers::layer(0),
+            // 按区域变化的迷雾外观：沼泽绿雾、山地灰云和紫色腐化
+            // Fog appearance varying by region: green swamp mist, grey mountain clouds and purple corruption
+            appearance: Some(FogAppearanceMap::from_chunks(
+                &mut images,
+                FogOfWarConfig::default().chunk_size,
+                ChunkCoord { x: -2, y: -2 },
+                UVec2::splat(4),
+                region_appearance,
+            )),
+            // 默认使用噪声纹理，按 F 切换到程序化噪声
+            // Use the noise texture by default, press F to switch to procedural noise
+            procedural_noise: None,
+            // 视野边界发光轮廓，按 G 切换
+            // Glowing outline at the edge of sight, press G to toggle
+            edge: Some(FogEdge::default()),
+            // 已探索区域覆盖迷雾，按 H 切换为去色调暗
+            // Explored areas are fogged, press H to desaturate and darken them instead
+            explored_style: FogExploredStyle::Fog,
+            // 用噪声扰动视野边界，形成飘动的卷须
+            // Distort vision boundaries with noise into drifting wisps
+            boundary_distortion: 0.35,
+            boundary_frequency: 4.0,
+            boundary_speed: 0.25,
+            // 渲染默认迷雾层
+            // Render the default fog layer
+            layer: None,
         },
+        // 相机同时渲染第 1 层，用于不受迷雾覆盖的标记
+        // The camera also renders layer 1, used for markers that are not fogged
+        RenderLayers::from_layers(&[0, 1]),
         MainCamera,
     ));
 
+    // 第 1 层上的标记始终绘制在迷雾之上
+    // Markers on layer 1 are always drawn above the fog
+    commands.spawn((
+        Sprite {
+            color: Color::WHITE,
+            custom_size: Some(Vec2::new(40.0, 40.0)),
+            ..default()
+        },
+        Transform::from_translation(Vec3::new(-300.0, -200.0, 0.0)),
+        RenderLayers::layer(1),
+    ));
+
     // 生成一个红色方块来测试基本渲染功能，并添加视野提供者组件
     // Spawn a red square to test basic rendering functionality and add vision provider component
     commands.spawn((
@@ -94,14 +170,19 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             custom_size: Some(Vec2::new(100.0, 100.0)),
             ..default()
         },
-        Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
+        // z 高于世界空间迷雾图层，在该模式下绘制在迷雾之上
+        // Z above the world-space fog layer, drawn over the fog in that mode
+        Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)),
         VisionProvider {
             range: 50.0, // 增大视野范围 / Increased vision range
+            // 红外色调 / Infrared tint
+            tint: Color::srgba(1.0, 0.2, 0.2, 0.4),
+            ..default()
         },
     ));
     
-    // 生成额外的视野提供者
-    // Spawn additional vision providers
+    // 生成额外的视野提供者，使用探照灯形状的视野印章
+    // Spawn an additional vision provider with a searchlight shaped vision stamp
     commands.spawn((
         Sprite {
             color: GOLD.into(),
@@ -110,8 +191,11 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         },
         Transform::from_translation(Vec3::new(200.0, 200.0, 0.0)),
         VisionProvider {
-            range: 40.0,
+            range: 160.0,
+            stamp: Some(images.add(searchlight_stamp())),
+            ..default()
         },
+        Searchlight,
     ));
 
     // 颜色渐变条作为参考，并添加视野提供者组件到部分方块
@@ -136,11 +220,58 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         if i % 2 == 0 {
             entity_commands.insert(VisionProvider {
                 range: 30.0 + (i as f32 * 15.0), // 不同范围的视野 / Different vision ranges
+                ..default()
             });
+        } else {
+            // 奇数索引的方块离开视野时留下残影
+            // Blocks with odd indices leave a ghost when they drop out of vision
+            entity_commands.insert(LeavesGhost);
         }
     }
 }
 
+/// 生成探照灯视野印章：指向 +X 的扇形光束
+/// Build a searchlight vision stamp: a wedge shaped beam pointing towards +X
+fn searchlight_stamp() -> Image {
+    const SIZE: u32 = 64;
+    let mut data = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    for row in 0..SIZE {
+        for column in 0..SIZE {
+            // 图像第一行位于上方
+            // The first image row is at the top
+            let offset = Vec2::new(
+                column as f32 + 0.5 - SIZE as f32 / 2.0,
+                SIZE as f32 / 2.0 - row as f32 - 0.5,
+            );
+            let in_beam = offset.x > 0.0
+                && offset.y.abs() < offset.x * 0.4
+                && offset.length() < SIZE as f32 / 2.0;
+            let value = if in_beam { 255 } else { 0 };
+            data.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+/// 旋转探照灯
+/// Rotate the searchlight
+fn rotate_searchlight(time: Res<Time>, mut query: Query<&mut Transform, With<Searchlight>>) {
+    for mut transform in &mut query {
+        transform.rotate_z(time.delta_secs() * 0.5);
+    }
+}
+
 // 相机移动系统
 // Camera movement system
 fn camera_movement(
@@ -179,6 +310,7 @@ fn update_fog_settings(
     time: Res<Time>,
     asset_server: Res<AssetServer>,
     mut fog_settings: Single<&mut FogMaterial>,
+    mut environment: ResMut<VisionEnvironment>,
 ) {
     let delta = time.delta_secs();
     let mut changed = false;
@@ -203,6 +335,57 @@ fn update_fog_settings(
         }
         changed = true;
     }
+    // 切换程序化噪声
+    // Toggle procedural noise
+    if keyboard.just_pressed(KeyCode::KeyF) {
+        fog_settings.procedural_noise = match fog_settings.procedural_noise {
+            Some(_) => None,
+            None => Some(FogNoise::default()),
+        };
+        changed = true;
+    }
+    // 切换视野边界轮廓
+    // Toggle the visibility edge outline
+    if keyboard.just_pressed(KeyCode::KeyG) {
+        fog_settings.edge = match fog_settings.edge {
+            Some(_) => None,
+            None => Some(FogEdge::default()),
+        };
+        changed = true;
+    }
+    // 切换已探索区域的表现方式
+    // Toggle how explored areas are shown
+    if keyboard.just_pressed(KeyCode::KeyH) {
+        fog_settings.explored_style = match fog_settings.explored_style {
+            FogExploredStyle::Fog => FogExploredStyle::Grade {
+                saturation: 0.1,
+                brightness: 0.5,
+                lut: None,
+            },
+            FogExploredStyle::Grade { .. } => FogExploredStyle::Fog,
+        };
+        changed = true;
+    }
+    // 切换昼夜，夜晚视野减半
+    // Toggle day/night, halving vision at night
+    if keyboard.just_pressed(KeyCode::KeyT) {
+        environment.range_multiplier = if environment.range_multiplier < 1.0 {
+            1.0
+        } else {
+            0.5
+        };
+        changed = true;
+    }
+
+    // 切换渲染模式
+    // Toggle render mode
+    if keyboard.just_pressed(KeyCode::KeyM) {
+        fog_settings.render_mode = match fog_settings.render_mode {
+            FogRenderMode::Overlay => FogRenderMode::WorldSpace { z: 1.0 },
+            FogRenderMode::WorldSpace { .. } => FogRenderMode::Overlay,
+        };
+        changed = true;
+    }
     if keyboard.just_pressed(KeyCode::Digit2) {
         // 红色迷雾 / Red fog
         fog_settings.color = Color::Srgba(Srgba::new(0.4, 0.1, 0.1, 1.0));
@@ -256,7 +439,7 @@ fn update_fog_settings(
     // If settings changed, display current settings
     if changed {
         println!(
-            "Fog Settings: Color: {:?}, Noise Texture: {}, Intensity: {:.2}, Scale: {:.2}, Speed: {:.2}",
+            "Fog Settings: Color: {:?}, Noise Texture: {}, Intensity: {:.2}, Scale: {:.2}, Speed: {:.2}, Mode: {:?}",
             fog_settings.color,
             if fog_settings.noise_texture.is_some() {
                 "Enabled"
@@ -265,7 +448,8 @@ fn update_fog_settings(
             },
             fog_settings.noise_intensity,
             fog_settings.noise_scale,
-            fog_settings.noise_speed
+            fog_settings.noise_speed,
+            fog_settings.render_mode
         );
     }
 }
@@ -347,20 +531,50 @@ fn update_fps_text(
     mut query: Query<&mut TextSpan, With<FpsText>>,
 ) {
     for mut span in &mut query {
-        if let Some(fps) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FPS) {
-            if let Some(value) = fps.smoothed() {
-                // 更新 FPS 文本值
-                // Update FPS text value
-                **span = format!("{value:.1}");
-            }
+        if let Some(value) = diagnostics
+            .get(&FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.smoothed())
+        {
+            // 更新 FPS 文本值
+            // Update FPS text value
+            **span = format!("{value:.1}");
         }
     }
 }
 
+/// 示例地图的区域迷雾外观
+/// Regional fog appearance of the example map
+fn region_appearance(coord: ChunkCoord) -> FogAppearance {
+    match (coord.x, coord.y) {
+        (-1, 0) => FogAppearance {
+            color: Color::srgb(0.35, 0.05, 0.4),
+            density: 1.0,
+            noise_intensity: 1.0,
+        },
+        (x, y) if x < 0 && y < 0 => FogAppearance {
+            color: Color::srgb(0.1, 0.3, 0.12),
+            density: 0.85,
+            noise_intensity: 0.8,
+        },
+        (x, y) if x >= 0 && y >= 0 => FogAppearance {
+            color: Color::srgb(0.45, 0.45, 0.5),
+            density: 0.95,
+            noise_intensity: 0.4,
+        },
+        _ => FogAppearance {
+            color: Color::srgb(0.1, 0.2, 0.4),
+            density: 1.0,
+            noise_intensity: 1.0,
+        },
+    }
+}
+
 /// 更新迷雾设置文本系统
 /// Update fog settings text system
 fn update_fog_settings_text(
     fog_settings: Single<&FogMaterial>,
+    environment: Res<VisionEnvironment>,
+    chunk_manager: Res<FogChunkManager>,
     mut query: Query<&mut Text, With<FogSettingsText>>,
 ) {
     for mut text in &mut query {
@@ -386,12 +600,21 @@ fn update_fog_settings_text(
         // 更新设置文本
         // Update settings text
         **text = format!(
-            " Color: {}\n Noise Texture: {}\n Intensity: {:.2} (Q/E)\n Scale: {:.2} (Z/X)\n Speed: {:.2} (C/V)\n  Press N to toggle noise\n ",
+            " Color: {}\n Noise Texture: {}\n Intensity: {:.2} (Q/E)\n Scale: {:.2} (Z/X)\n Speed: {:.2} (C/V)\n Mode: {:?} (M)\n Vision: x{:.2} (T)\n Procedural Noise: {} (F)\n Edge Outline: {} (G)\n Explored: {} (H)\n Frozen: {} (B)\n  Press N to toggle noise\n  Press R to reveal, U for a flare, K to reveal all, L to reset\n ",
             color_text,
             noise_text,
             fog_settings.noise_intensity,
             fog_settings.noise_scale,
             fog_settings.noise_speed,
+            fog_settings.render_mode,
+            environment.range_multiplier,
+            fog_settings.procedural_noise.is_some(),
+            fog_settings.edge.is_some(),
+            match fog_settings.explored_style {
+                FogExploredStyle::Fog => "Fog",
+                FogExploredStyle::Grade { .. } => "Grade",
+            },
+            chunk_manager.is_frozen(),
         );
     }
 }
@@ -414,3 +637,73 @@ fn text_color_animation(
         );
     }
 }
+
+/// 迷雾命令：R 揭示相机周围 5 秒，U 在相机处发射照明弹，K 揭示整张地图，L 重置迷雾，B 冻结迷雾
+/// Fog commands: R reveals around the camera for 5 seconds, U fires a flare at the camera, K reveals the whole map, L resets the fog, B freezes it
+fn fog_commands(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    camera: Single<&Transform, With<MainCamera>>,
+    mut fog: FogCommands,
+    mut frozen: Local<bool>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyU) {
+        commands.spawn((
+            TemporaryReveal::new(3.0),
+            VisionProvider {
+                range: 120.0,
+                // 照明弹色调 / Flare tint
+                tint: Color::srgba(1.0, 0.8, 0.4, 0.3),
+                ..default()
+            },
+            Transform::from_translation(camera.translation.with_z(0.0)),
+        ));
+    }
+    if keyboard.just_pressed(KeyCode::KeyR) {
+        fog.reveal_area(
+            FogShape::Circle {
+                center: camera.translation.truncate(),
+                radius: 150.0,
+            },
+            Some(5.0),
+        );
+    }
+    if keyboard.just_pressed(KeyCode::KeyK) {
+        fog.reveal_all();
+    }
+    if keyboard.just_pressed(KeyCode::KeyL) {
+        fog.reset_fog();
+    }
+    if keyboard.just_pressed(KeyCode::KeyB) {
+        *frozen = !*frozen;
+        fog.freeze_fog(*frozen);
+    }
+}
+
+/// 按 P 保存迷雾快照（区块掩码和 GPU 可见性纹理）
+/// Press P to save fog snapshots (chunk mask and GPU visibility texture)
+fn save_fog_snapshot(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    chunk_manager: Res<FogChunkManager>,
+    snapshot: Res<FogVisibilitySnapshot>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyP) {
+        let mask = export_chunk_mask(
+            &chunk_manager,
+            ChunkCoord { x: -16, y: -16 },
+            UVec2::splat(32),
+        );
+        if let Err(error) = save_mask_png(&mask, "fog_chunks.png") {
+            error!("{error}");
+        }
+        snapshot.request();
+    }
+
+    // 快照在请求后的下一帧可用
+    // The snapshot becomes available the frame after the request
+    if let Some(image) = snapshot.take()
+        && let Err(error) = save_mask_png(&image, "fog_visibility.png")
+    {
+        error!("{error}");
+    }
+}
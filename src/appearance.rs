@@ -0,0 +1,114 @@
+use crate::chunk::ChunkCoord;
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+/// 世界空间迷雾外观图，按区域改变迷雾颜色、密度和噪声强度
+/// World-space fog appearance map, varying fog color, density and noise intensity by region
+///
+/// 图像覆盖 `bounds` 描述的世界区域，第一行位于 y 最大处；区域外使用 [`FogMaterial`](crate::prelude::FogMaterial) 的设置
+/// The images cover the world area described by `bounds`, with the first row at the greatest y; outside it the [`FogMaterial`](crate::prelude::FogMaterial) settings apply
+#[derive(Clone, Debug, Reflect)]
+pub struct FogAppearanceMap {
+    /// 颜色与密度图：RGB 为迷雾颜色，A 为迷雾密度
+    /// Color and density map: RGB is the fog color, A is the fog density
+    pub color_density: Handle<Image>,
+    /// 噪声强度图（R 通道），为空时使用材质的噪声强度
+    /// Noise intensity map (R channel); the material noise intensity is used when empty
+    pub noise_intensity: Option<Handle<Image>>,
+    /// 外观图覆盖的世界区域
+    /// World area covered by the appearance map
+    pub bounds: Rect,
+}
+
+/// 单个区域的迷雾外观
+/// Fog appearance of a single region
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct FogAppearance {
+    /// 迷雾颜色
+    /// Fog color
+    pub color: Color,
+    /// 迷雾密度 (0.0-1.0)，0 表示该区域没有迷雾
+    /// Fog density (0.0-1.0); 0 means the region has no fog
+    pub density: f32,
+    /// 噪声强度 (0.0-1.0)
+    /// Noise intensity (0.0-1.0)
+    pub noise_intensity: f32,
+}
+
+impl Default for FogAppearance {
+    fn default() -> Self {
+        Self {
+            color: Color::BLACK,
+            density: 1.0,
+            noise_intensity: 1.0,
+        }
+    }
+}
+
+impl FogAppearanceMap {
+    /// 由逐区块数据生成外观图，每个像素对应一个区块
+    /// Build an appearance map from per-chunk data, one pixel per chunk
+    ///
+    /// `origin` 是左下角区块，`size` 为区块数量；区块按正方形布局映射到世界
+    /// `origin` is the bottom-left chunk and `size` the number of chunks; chunks map to the world with the square layout
+    pub fn from_chunks(
+        images: &mut Assets<Image>,
+        chunk_size: f32,
+        origin: ChunkCoord,
+        size: UVec2,
+        mut appearance: impl FnMut(ChunkCoord) -> FogAppearance,
+    ) -> Self {
+        let mut color_density = Vec::with_capacity((size.x * size.y * 4) as usize);
+        let mut noise_intensity = Vec::with_capacity((size.x * size.y) as usize);
+        for row in 0..size.y {
+            for column in 0..size.x {
+                let region = appearance(ChunkCoord {
+                    x: origin.x + column as i32,
+                    y: origin.y + (size.y - 1 - row) as i32,
+                });
+                let color = region.color.to_srgba();
+                color_density.extend_from_slice(&[
+                    unorm8(color.red),
+                    unorm8(color.green),
+                    unorm8(color.blue),
+                    unorm8(region.density),
+                ]);
+                noise_intensity.push(unorm8(region.noise_intensity));
+            }
+        }
+
+        let min = Vec2::new(origin.x as f32, origin.y as f32) * chunk_size;
+        Self {
+            color_density: images.add(appearance_image(
+                size,
+                color_density,
+                TextureFormat::Rgba8UnormSrgb,
+            )),
+            noise_intensity: Some(images.add(appearance_image(
+                size,
+                noise_intensity,
+                TextureFormat::R8Unorm,
+            ))),
+            bounds: Rect::from_corners(min, min + size.as_vec2() * chunk_size),
+        }
+    }
+}
+
+fn unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn appearance_image(size: UVec2, data: Vec<u8>, format: TextureFormat) -> Image {
+    Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        format,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
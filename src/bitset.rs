@@ -0,0 +1,252 @@
+use crate::chunk::ChunkCoord;
+use bevy::math::IVec2;
+use bevy::utils::HashMap;
+
+/// 区块页的边长（区块数），每页用 64 个 `u64` 存储 64×64 个区块
+/// Side length of a chunk page (in chunks); each page stores 64×64 chunks in 64 `u64`s
+pub const CHUNK_PAGE_SIZE: i32 = 64;
+
+type ChunkPage = [u64; CHUNK_PAGE_SIZE as usize];
+
+/// 按区域分页的区块位集
+/// Region-paged chunk bitset
+///
+/// 只为包含区块的 64×64 区域分配页，查询接口与 `HashSet<ChunkCoord>` 一致，
+/// 在超大地图上比哈希集合占用更少的内存
+/// Pages are only allocated for 64×64 regions that contain chunks; the query API matches `HashSet<ChunkCoord>`,
+/// using far less memory than a hash set on huge maps
+#[derive(Clone, Debug, Default)]
+pub struct ChunkBitSet {
+    pages: HashMap<IVec2, Box<ChunkPage>>,
+    len: usize,
+}
+
+impl ChunkBitSet {
+    /// 创建空位集
+    /// Create an empty bitset
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 区块数量
+    /// Number of chunks
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 是否为空
+    /// Whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 是否包含区块
+    /// Whether the set contains a chunk
+    pub fn contains(&self, coord: &ChunkCoord) -> bool {
+        let (page, row, bit) = locate(coord);
+        self.pages
+            .get(&page)
+            .is_some_and(|words| words[row] & bit != 0)
+    }
+
+    /// 插入区块，区块此前不存在时返回 `true`
+    /// Insert a chunk, returning `true` if it was not present
+    pub fn insert(&mut self, coord: ChunkCoord) -> bool {
+        let (page, row, bit) = locate(&coord);
+        let words = self
+            .pages
+            .entry(page)
+            .or_insert_with(|| Box::new([0; CHUNK_PAGE_SIZE as usize]));
+        let inserted = words[row] & bit == 0;
+        words[row] |= bit;
+        self.len += inserted as usize;
+        inserted
+    }
+
+    /// 移除区块，区块此前存在时返回 `true`
+    /// Remove a chunk, returning `true` if it was present
+    pub fn remove(&mut self, coord: &ChunkCoord) -> bool {
+        let (page, row, bit) = locate(coord);
+        let Some(words) = self.pages.get_mut(&page) else {
+            return false;
+        };
+        let removed = words[row] & bit != 0;
+        words[row] &= !bit;
+        self.len -= removed as usize;
+        if removed && words.iter().all(|word| *word == 0) {
+            self.pages.remove(&page);
+        }
+        removed
+    }
+
+    /// 清空位集，保留已分配的页以便复用
+    /// Clear the bitset, keeping allocated pages for reuse
+    pub fn clear(&mut self) {
+        for words in self.pages.values_mut() {
+            words.fill(0);
+        }
+        self.len = 0;
+    }
+
+    /// 遍历所有区块（无序）
+    /// Iterate over all chunks (unordered)
+    pub fn iter(&self) -> impl Iterator<Item = ChunkCoord> + '_ {
+        self.pages.iter().flat_map(|(page, words)| {
+            let origin = *page * CHUNK_PAGE_SIZE;
+            words.iter().enumerate().flat_map(move |(row, word)| {
+                let mut bits = *word;
+                core::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+                    let column = bits.trailing_zeros();
+                    bits &= bits - 1;
+                    Some(ChunkCoord {
+                        x: origin.x + column as i32,
+                        y: origin.y + row as i32,
+                    })
+                })
+            })
+        })
+    }
+}
+
+impl Extend<ChunkCoord> for ChunkBitSet {
+    fn extend<T: IntoIterator<Item = ChunkCoord>>(&mut self, iter: T) {
+        for coord in iter {
+            self.insert(coord);
+        }
+    }
+}
+
+impl FromIterator<ChunkCoord> for ChunkBitSet {
+    fn from_iter<T: IntoIterator<Item = ChunkCoord>>(iter: T) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+/// 区块所在的页、页内行和位掩码
+/// Page, row within the page and bit mask of a chunk
+fn locate(coord: &ChunkCoord) -> (IVec2, usize, u64) {
+    let coord = IVec2::new(coord.x, coord.y);
+    let page = coord.div_euclid(IVec2::splat(CHUNK_PAGE_SIZE));
+    let local = coord.rem_euclid(IVec2::splat(CHUNK_PAGE_SIZE));
+    (page, local.y as usize, 1 << local.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coord(x: i32, y: i32) -> ChunkCoord {
+        ChunkCoord { x, y }
+    }
+
+    fn sorted(set: &ChunkBitSet) -> Vec<(i32, i32)> {
+        let mut coords: Vec<_> = set.iter().map(|coord| (coord.x, coord.y)).collect();
+        coords.sort();
+        coords
+    }
+
+    #[test]
+    fn negative_coordinates_use_their_own_pages() {
+        let mut set = ChunkBitSet::new();
+        assert!(set.insert(coord(-1, -1)));
+        assert!(set.insert(coord(0, 0)));
+        assert!(!set.insert(coord(-1, -1)));
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.pages.len(), 2);
+        assert!(set.contains(&coord(-1, -1)));
+        assert!(!set.contains(&coord(-1, 0)));
+        assert!(!set.contains(&coord(1, 1)));
+        assert_eq!(locate(&coord(-1, -1)), (IVec2::NEG_ONE, 63, 1 << 63));
+        assert_eq!(locate(&coord(-64, -65)), (IVec2::new(-1, -2), 63, 1));
+    }
+
+    #[test]
+    fn page_boundaries() {
+        let edges = [-65, -64, -63, -1, 0, 1, 63, 64, 65, 127, 128];
+        let mut set = ChunkBitSet::new();
+        for &x in &edges {
+            for &y in &edges {
+                assert!(set.insert(coord(x, y)));
+            }
+        }
+
+        assert_eq!(set.len(), edges.len() * edges.len());
+        for &x in &edges {
+            for &y in &edges {
+                assert!(set.contains(&coord(x, y)));
+                assert!(!set.contains(&coord(x, y + 1000)));
+            }
+        }
+        // 页坐标 -2 到 2 (Page coordinates -2 to 2)
+        assert_eq!(set.pages.len(), 25);
+    }
+
+    #[test]
+    fn removing_the_last_chunk_frees_its_page() {
+        let mut set = ChunkBitSet::new();
+        set.insert(coord(-64, 5));
+        set.insert(coord(-1, 63));
+        set.insert(coord(0, 0));
+
+        assert!(set.remove(&coord(-64, 5)));
+        assert_eq!(set.pages.len(), 2);
+        assert!(!set.remove(&coord(-64, 5)));
+        assert!(set.remove(&coord(-1, 63)));
+        assert_eq!(set.pages.len(), 1);
+        assert!(!set.remove(&coord(-1, 62)));
+        assert_eq!(set.len(), 1);
+
+        assert!(set.remove(&coord(0, 0)));
+        assert!(set.is_empty());
+        assert!(set.pages.is_empty());
+        assert_eq!(set.iter().count(), 0);
+    }
+
+    #[test]
+    fn clear_keeps_pages_but_empties_the_set() {
+        let mut set: ChunkBitSet = [coord(3, -70), coord(200, 4)].into_iter().collect();
+        set.clear();
+
+        assert!(set.is_empty());
+        assert_eq!(set.pages.len(), 2);
+        assert!(!set.contains(&coord(3, -70)));
+        assert_eq!(set.iter().count(), 0);
+        assert!(set.insert(coord(3, -70)));
+    }
+
+    #[test]
+    fn iter_yields_every_chunk_once() {
+        let coords = [
+            (-130, 7),
+            (-64, -64),
+            (-1, 0),
+            (0, -1),
+            (0, 0),
+            (63, 63),
+            (64, 0),
+            (5, 5),
+        ];
+        let set: ChunkBitSet = coords.iter().map(|&(x, y)| coord(x, y)).collect();
+
+        let mut expected = coords.to_vec();
+        expected.sort();
+        assert_eq!(sorted(&set), expected);
+        assert_eq!(set.len(), coords.len());
+    }
+
+    #[test]
+    fn iter_within_a_page_goes_row_by_row() {
+        let set: ChunkBitSet = [coord(-2, -63), coord(-60, -64), coord(-1, -64)]
+            .into_iter()
+            .collect();
+
+        let coords: Vec<_> = set.iter().map(|coord| (coord.x, coord.y)).collect();
+        assert_eq!(coords, [(-60, -64), (-1, -64), (-2, -63)]);
+    }
+}
@@ -1,16 +1,189 @@
 use bevy::{prelude::*, render::sync_world::SyncToRenderWorld};
-use std::collections::{HashMap, HashSet};
+use bevy::utils::Parallel;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use bevy::asset::RenderAssetUsages;
 use bevy::render::extract_component::ExtractComponent;
-use crate::fog::FogOfWarConfig;
+use bevy::render::renderer::RenderDevice;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use crate::bitset::ChunkBitSet;
+use crate::environment::VisionEnvironment;
+use crate::fog::{FogMaterial, FogOfWarConfig};
+use crate::layer::{FogLayer, InFogLayer};
+use crate::level::FogLevelState;
+use crate::vision_layers::VisionLayers;
+use crate::height::VisionHeightMap;
+use crate::stamp::{VISION_STAMP_THRESHOLD, VisionStamps, provider_rotation, stamp_uv};
+use core::f32::consts::SQRT_2;
+
+const SQRT_3: f32 = 1.732_050_8;
 
 /// 地图区块坐标
 /// Map chunk coordinates
-#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
 pub struct ChunkCoord {
     pub x: i32,
     pub y: i32,
 }
 
+/// 区块网格布局
+/// Chunk grid layout
+///
+/// 六边形布局使用轴向坐标，`ChunkCoord::x` 为 q，`ChunkCoord::y` 为 r；
+/// 此时 `chunk_size` 为六边形中心到顶点的距离
+/// Hex layouts use axial coordinates, with `ChunkCoord::x` as q and `ChunkCoord::y` as r;
+/// `chunk_size` is then the distance from a hex center to its corners
+///
+/// 判别值与迷雾着色器中的 `chunk_layout` 一致
+/// The discriminants match `chunk_layout` in the fog shader
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum ChunkLayout {
+    /// 正方形网格，`chunk_size` 为边长
+    /// Square grid, `chunk_size` is the side length
+    #[default]
+    Square = 0,
+    /// 尖顶六边形网格
+    /// Pointy-top hex grid
+    HexPointy = 1,
+    /// 平顶六边形网格
+    /// Flat-top hex grid
+    HexFlat = 2,
+}
+
+impl ChunkLayout {
+    const SQUARE_NEIGHBOURS: [IVec2; 8] = [
+        IVec2::new(1, 0),
+        IVec2::new(1, 1),
+        IVec2::new(0, 1),
+        IVec2::new(-1, 1),
+        IVec2::new(-1, 0),
+        IVec2::new(-1, -1),
+        IVec2::new(0, -1),
+        IVec2::new(1, -1),
+    ];
+    const HEX_NEIGHBOURS: [IVec2; 6] = [
+        IVec2::new(1, 0),
+        IVec2::new(1, -1),
+        IVec2::new(0, -1),
+        IVec2::new(-1, 0),
+        IVec2::new(-1, 1),
+        IVec2::new(0, 1),
+    ];
+
+    /// 是否为六边形布局
+    /// Whether this is a hex layout
+    pub fn is_hex(self) -> bool {
+        self != Self::Square
+    }
+
+    /// 世界坐标所在的区块
+    /// Chunk containing a world position
+    pub fn world_to_chunk(self, position: Vec2, chunk_size: f32) -> ChunkCoord {
+        let p = position / chunk_size;
+        match self {
+            Self::Square => ChunkCoord {
+                x: p.x.floor() as i32,
+                y: p.y.floor() as i32,
+            },
+            Self::HexPointy => {
+                hex_round(Vec2::new(SQRT_3 / 3.0 * p.x - p.y / 3.0, 2.0 / 3.0 * p.y))
+            }
+            Self::HexFlat => {
+                hex_round(Vec2::new(2.0 / 3.0 * p.x, -p.x / 3.0 + SQRT_3 / 3.0 * p.y))
+            }
+        }
+    }
+
+    /// 区块中心的世界坐标
+    /// World position of a chunk center
+    pub fn chunk_center(self, coord: ChunkCoord, chunk_size: f32) -> Vec2 {
+        let (q, r) = (coord.x as f32, coord.y as f32);
+        let center = match self {
+            Self::Square => Vec2::new(q + 0.5, r + 0.5),
+            Self::HexPointy => Vec2::new(SQRT_3 * q + SQRT_3 / 2.0 * r, 1.5 * r),
+            Self::HexFlat => Vec2::new(1.5 * q, SQRT_3 / 2.0 * q + SQRT_3 * r),
+        };
+        center * chunk_size
+    }
+
+    /// 相邻区块中心之间的最小距离
+    /// Smallest distance between neighbouring chunk centers
+    pub fn chunk_spacing(self, chunk_size: f32) -> f32 {
+        if self.is_hex() {
+            SQRT_3 * chunk_size
+        } else {
+            chunk_size
+        }
+    }
+
+    /// 相邻区块（正方形 8 个，六边形 6 个）
+    /// Neighbouring chunks (8 for squares, 6 for hexes)
+    pub fn neighbours(self, coord: ChunkCoord) -> impl Iterator<Item = ChunkCoord> {
+        let offsets: &'static [IVec2] = if self.is_hex() {
+            &Self::HEX_NEIGHBOURS
+        } else {
+            &Self::SQUARE_NEIGHBOURS
+        };
+        offsets.iter().map(move |offset| ChunkCoord {
+            x: coord.x + offset.x,
+            y: coord.y + offset.y,
+        })
+    }
+
+    /// 两个区块之间的距离（以区块为单位）：正方形为欧氏距离，六边形为六边形步数
+    /// Distance between two chunks in chunks: Euclidean for squares, hex steps for hexes
+    pub fn chunk_distance(self, a: ChunkCoord, b: ChunkCoord) -> f32 {
+        let (dx, dy) = (a.x - b.x, a.y - b.y);
+        if self.is_hex() {
+            ((dx.abs() + dy.abs() + (dx + dy).abs()) / 2) as f32
+        } else {
+            ((dx * dx + dy * dy) as f32).sqrt()
+        }
+    }
+
+    /// 以某区块为中心、半径内的所有区块（正方形为方形范围，六边形为六边形范围）
+    /// All chunks within a radius of a center chunk (a square area for squares, a hexagon for hexes)
+    pub fn chunks_in_range(
+        self,
+        center: ChunkCoord,
+        radius: i32,
+    ) -> impl Iterator<Item = ChunkCoord> {
+        let hex = self.is_hex();
+        (-radius..=radius).flat_map(move |x| {
+            // 轴向坐标中 |x| + |y| + |x + y| <= 2 * radius 的范围
+            // In axial coordinates the range satisfies |x| + |y| + |x + y| <= 2 * radius
+            let (min_y, max_y) = if hex {
+                ((-radius).max(-x - radius), radius.min(-x + radius))
+            } else {
+                (-radius, radius)
+            };
+            (min_y..=max_y).map(move |y| ChunkCoord {
+                x: center.x + x,
+                y: center.y + y,
+            })
+        })
+    }
+}
+
+/// 将分数轴向坐标取整为最近的六边形
+/// Round fractional axial coordinates to the nearest hex
+fn hex_round(axial: Vec2) -> ChunkCoord {
+    let cube = Vec3::new(axial.x, axial.y, -axial.x - axial.y);
+    let rounded = cube.round();
+    let diff = (rounded - cube).abs();
+    let (q, r) = if diff.x > diff.y && diff.x > diff.z {
+        (-rounded.y - rounded.z, rounded.y)
+    } else if diff.y > diff.z {
+        (rounded.x, -rounded.x - rounded.z)
+    } else {
+        (rounded.x, rounded.y)
+    };
+    ChunkCoord {
+        x: q as i32,
+        y: r as i32,
+    }
+}
+
 /// 区块状态
 /// Chunk state
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Reflect)]
@@ -31,16 +204,269 @@ pub struct FogChunk {
     pub last_visible_time: f32,
 }
 
+/// 已探索区域纹理，每个像素对应一个区块，供着色器在世界空间采样
+/// Explored area texture, one pixel per chunk, sampled by shaders in world space
+///
+/// R 通道为已探索，G 通道为由 [`FogCommands`](crate::prelude::FogCommands) 揭示
+/// The R channel is explored, the G channel revealed by [`FogCommands`](crate::prelude::FogCommands)
+///
+/// 纹理覆盖已探索区域外加增长余量，只写入状态变化的区块；每边不超过 GPU 的 `max_texture_dimension_2d`，
+/// 更大的已探索区域只保留以当前可见区块为中心的窗口，窗口外按未探索显示
+/// The texture covers the explored area plus room to grow and only chunks whose state changed are written; each side
+/// stays within the GPU `max_texture_dimension_2d`, so larger explored areas keep a window centred on the visible chunks
+/// and show as unexplored outside it
+#[derive(Resource, Component, Clone, Default)]
+pub struct FogExploredTexture {
+    /// 已探索纹理（Rg8Unorm，255 表示已探索或已揭示），第一行位于 y 最大处
+    /// Explored texture (Rg8Unorm, 255 means explored or revealed), with the first row at the greatest y
+    pub image: Option<Handle<Image>>,
+    /// 纹理左下角的区块
+    /// Chunk at the bottom-left of the texture
+    pub origin: ChunkCoord,
+    /// 纹理覆盖的区块数量
+    /// Number of chunks covered by the texture
+    pub size: UVec2,
+    /// 生成纹理时的区块布局
+    /// Chunk layout the texture was built with
+    pub layout: ChunkLayout,
+    /// 生成纹理时的区块大小
+    /// Chunk size the texture was built with
+    pub chunk_size: f32,
+    /// 生成纹理时的世界到迷雾空间变换
+    /// World to fog space transform the texture was built with
+    pub world_to_fog: Mat2,
+    /// 是否揭示整张地图
+    /// Whether the whole map is revealed
+    pub reveal_all: bool,
+    /// 已探索区块的范围（包含两端）
+    /// Bounds of the explored chunks (inclusive)
+    bounds: Option<(IVec2, IVec2)>,
+    /// 生成纹理时的迷雾状态替换次数
+    /// Fog state revision when the texture was built
+    revision: u32,
+}
+
 /// 存储所有激活的区块
 /// Stores all active chunks
-#[derive(Resource, Default)]
+#[derive(Resource, Component, Default)]
 pub struct FogChunkManager {
     /// 激活的区块映射 (Active chunk map)
     pub active_chunks: HashMap<ChunkCoord, Entity>,
     /// 当前可见的区块 (Currently visible chunks)
-    pub visible_chunks: HashSet<ChunkCoord>,
+    pub visible_chunks: ChunkBitSet,
     /// 已探索的区块 (Explored chunks)
-    pub explored_chunks: HashSet<ChunkCoord>,
+    pub explored_chunks: ChunkBitSet,
+    /// 每个视野提供者上次计算的覆盖区块 (Footprint of each vision provider when last computed)
+    footprints: HashMap<Entity, ChunkBitSet>,
+    /// 覆盖每个可见区块的视野来源数量 (Number of vision sources covering each visible chunk)
+    coverage: HashMap<ChunkCoord, u32>,
+    /// 由命令揭示的区域 (Areas revealed by commands)
+    reveals: Vec<FogReveal>,
+    /// 是否揭示整张地图 (Whether the whole map is revealed)
+    reveal_all: bool,
+    /// 是否冻结迷雾 (Whether the fog is frozen)
+    frozen: bool,
+    /// 上一帧是否冻结 (Whether the fog was frozen last frame)
+    was_frozen: bool,
+    /// 覆盖区块已清空，下次更新时重新计算所有提供者 (Footprints were dropped, so every provider is recomputed on the next update)
+    footprints_stale: bool,
+    /// 等待更新区块实体的区块 (Chunks whose entities await an update)
+    touched: Vec<ChunkCoord>,
+    /// 已探索或揭示状态变化、等待写入已探索纹理的区块 (Chunks whose explored or revealed state changed, awaiting a write to the explored texture)
+    texture_dirty: Vec<ChunkCoord>,
+    /// 整体替换迷雾状态的次数，变化时重建已探索纹理 (Number of times the whole fog state was replaced, rebuilding the explored texture when it changes)
+    revision: u32,
+}
+
+/// 由命令揭示的区域
+/// Area revealed by a command
+pub(crate) struct FogReveal {
+    chunks: ChunkBitSet,
+    /// 揭示结束的时间，为 `None` 时一直揭示
+    /// Time the reveal ends, revealed until concealed when `None`
+    expires_at: Option<f32>,
+}
+
+impl FogChunkManager {
+    /// 区块当前是否可见
+    /// Whether a chunk is currently visible
+    pub fn is_visible(&self, coord: &ChunkCoord) -> bool {
+        self.reveal_all || self.visible_chunks.contains(coord)
+    }
+
+    /// 是否揭示整张地图
+    /// Whether the whole map is revealed
+    pub fn is_revealed_all(&self) -> bool {
+        self.reveal_all
+    }
+
+    /// 迷雾是否冻结
+    /// Whether the fog is frozen
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// 增加区块的覆盖计数并标记为已探索
+    /// Increase the coverage count of a chunk and mark it explored
+    fn cover(&mut self, coord: ChunkCoord) {
+        if self.explored_chunks.insert(coord) {
+            self.texture_dirty.push(coord);
+        }
+        let count = self.coverage.entry(coord).or_insert(0);
+        *count += 1;
+        if *count == 1 && self.visible_chunks.insert(coord) {
+            self.touched.push(coord);
+        }
+    }
+
+    /// 减少区块的覆盖计数
+    /// Decrease the coverage count of a chunk
+    fn uncover(&mut self, coord: ChunkCoord) {
+        let Entry::Occupied(mut entry) = self.coverage.entry(coord) else {
+            return;
+        };
+        *entry.get_mut() -= 1;
+        if *entry.get() > 0 {
+            return;
+        }
+        entry.remove();
+        if self.visible_chunks.remove(&coord) {
+            self.touched.push(coord);
+        }
+    }
+
+    /// 标记所有激活的区块等待更新
+    /// Mark all active chunks as awaiting an update
+    fn touch_all(&mut self) {
+        self.touched.extend(self.active_chunks.keys().copied());
+    }
+
+    /// 揭示区块，`expires_at` 为揭示结束的时间
+    /// Reveal chunks, `expires_at` being the time the reveal ends
+    pub(crate) fn reveal(&mut self, chunks: ChunkBitSet, expires_at: Option<f32>) {
+        for coord in chunks.iter() {
+            self.cover(coord);
+        }
+        self.texture_dirty.extend(chunks.iter());
+        self.reveals.push(FogReveal { chunks, expires_at });
+    }
+
+    /// 将区块标记为已探索
+    /// Mark chunks as explored
+    pub(crate) fn explore(&mut self, chunks: impl IntoIterator<Item = ChunkCoord>) {
+        for coord in chunks {
+            if self.explored_chunks.insert(coord) {
+                self.touched.push(coord);
+                self.texture_dirty.push(coord);
+            }
+        }
+    }
+
+    /// 结束区块上的揭示，并将未被视野覆盖的区块恢复为未探索
+    /// End reveals on chunks and turn chunks no vision covers back to unexplored
+    pub(crate) fn conceal(&mut self, chunks: &ChunkBitSet) {
+        let mut reveals = core::mem::take(&mut self.reveals);
+        for reveal in &mut reveals {
+            for coord in chunks.iter() {
+                if reveal.chunks.remove(&coord) {
+                    self.uncover(coord);
+                }
+            }
+        }
+        reveals.retain(|reveal| !reveal.chunks.is_empty());
+        self.reveals = reveals;
+
+        for coord in chunks.iter() {
+            if !self.coverage.contains_key(&coord) && self.explored_chunks.remove(&coord) {
+                self.touched.push(coord);
+            }
+        }
+        self.texture_dirty.extend(chunks.iter());
+    }
+
+    /// 结束到期的揭示
+    /// End expired reveals
+    fn expire_reveals(&mut self, now: f32) {
+        if !self
+            .reveals
+            .iter()
+            .any(|reveal| reveal.expires_at.is_some_and(|end| end <= now))
+        {
+            return;
+        }
+        let (expired, kept): (Vec<_>, Vec<_>) = core::mem::take(&mut self.reveals)
+            .into_iter()
+            .partition(|reveal| reveal.expires_at.is_some_and(|end| end <= now));
+        self.reveals = kept;
+        for reveal in expired {
+            for coord in reveal.chunks.iter() {
+                self.uncover(coord);
+            }
+            self.texture_dirty.extend(reveal.chunks.iter());
+        }
+    }
+
+    /// 揭示或取消揭示整张地图
+    /// Reveal or stop revealing the whole map
+    pub(crate) fn set_reveal_all(&mut self, reveal_all: bool) {
+        if self.reveal_all != reveal_all {
+            self.reveal_all = reveal_all;
+            self.touch_all();
+        }
+    }
+
+    /// 冻结或解冻迷雾
+    /// Freeze or unfreeze the fog
+    pub(crate) fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    /// 重置迷雾：结束所有揭示，只保留当前视野内的区块为已探索
+    /// Reset the fog: end all reveals, keeping only chunks currently in vision explored
+    pub(crate) fn reset(&mut self) {
+        for reveal in core::mem::take(&mut self.reveals) {
+            for coord in reveal.chunks.iter() {
+                self.uncover(coord);
+            }
+        }
+        self.reveal_all = false;
+        self.explored_chunks.clear();
+        self.explored_chunks.extend(self.visible_chunks.iter());
+        self.touch_all();
+        self.revision += 1;
+    }
+
+    /// 换入另一个关卡的迷雾状态，返回当前关卡的状态
+    /// Swap in the fog state of another level, returning the state of the current one
+    pub(crate) fn swap_level(&mut self, level: FogLevelState) -> FogLevelState {
+        let FogLevelState {
+            explored_chunks,
+            reveals,
+            reveal_all,
+        } = level;
+
+        // 当前的视野覆盖属于离开的关卡，清空后按新关卡重新计算所有提供者
+        // The current vision coverage belongs to the outgoing level, so drop it and recompute every provider in the new one
+        self.footprints.clear();
+        self.coverage.clear();
+        self.visible_chunks.clear();
+        self.footprints_stale = true;
+        let stashed = FogLevelState {
+            explored_chunks: core::mem::replace(&mut self.explored_chunks, explored_chunks),
+            reveals: core::mem::take(&mut self.reveals),
+            reveal_all: core::mem::replace(&mut self.reveal_all, reveal_all),
+        };
+
+        for reveal in &reveals {
+            for coord in reveal.chunks.iter() {
+                self.cover(coord);
+            }
+        }
+        self.reveals = reveals;
+        self.touch_all();
+        self.revision += 1;
+        stashed
+    }
 }
 
 /// 视野提供者组件
@@ -51,190 +477,722 @@ pub struct VisionProvider {
     /// 视野范围（世界单位）
     /// Vision range (world units)
     pub range: f32,
+    /// 视野印章图像，以提供者为中心、随其旋转，边长为两倍视野范围；为 `None` 时使用圆形视野
+    /// Vision stamp image, centered on and rotating with the provider, twice the range wide; circular vision when `None`
+    pub stamp: Option<Handle<Image>>,
+    /// 视野色调，叠加在可见区域上；透明度控制强度，默认不着色
+    /// Vision tint added over the revealed area; alpha controls the strength, no tint by default
+    pub tint: Color,
+    /// 探测范围（世界单位），范围内的 [`Stealthed`](crate::prelude::Stealthed) 目标可见；默认为 0，不探测
+    /// Detection range (world units) within which [`Stealthed`](crate::prelude::Stealthed) targets are visible; 0 by default, detecting nothing
+    pub detection_range: f32,
+    /// 能看到的视野层，默认为地面和空中
+    /// Vision layers the provider sees, ground and air by default
+    pub layers: VisionLayers,
+    /// 视点高于地形的高度，见 [`VisionHeightMap`](crate::prelude::VisionHeightMap)
+    /// Height of the eye above the terrain, see [`VisionHeightMap`](crate::prelude::VisionHeightMap)
+    pub elevation: f32,
+    /// 提供者自身所在的视野层，默认为地面；位于地形不遮挡的层（如空中）时能看到更高的台地
+    /// Vision layer the provider itself lives on, ground by default; on a layer the terrain does not occlude (e.g. air)
+    /// it sees onto higher ledges
+    pub lives_on: VisionLayers,
+}
+
+impl Default for VisionProvider {
+    fn default() -> Self {
+        Self {
+            range: 100.0,
+            stamp: None,
+            tint: Color::NONE,
+            detection_range: 0.0,
+            layers: VisionLayers::GROUND | VisionLayers::AIR,
+            elevation: 0.0,
+            lives_on: VisionLayers::GROUND,
+        }
+    }
+}
+
+/// 视野提供者在迷雾空间中的视野形状
+/// Vision shape of a provider in fog space
+pub(crate) struct ProviderSight<'a> {
+    stamps: &'a VisionStamps,
+    environment: &'a VisionEnvironment,
+    heights: &'a VisionHeightMap,
+    vision: &'a VisionProvider,
+    /// 提供者的世界坐标 (Provider world position)
+    world_position: Vec2,
+    /// 提供者在迷雾空间中的位置 (Provider position in fog space)
+    position: Vec2,
+    /// 视点高度 (Eye height)
+    eye_height: f32,
+    rotation: f32,
+    stamp_layer: Option<u32>,
+}
+
+impl<'a> ProviderSight<'a> {
+    pub(crate) fn new(
+        config: &FogOfWarConfig,
+        environment: &'a VisionEnvironment,
+        stamps: &'a VisionStamps,
+        heights: &'a VisionHeightMap,
+        transform: &GlobalTransform,
+        vision: &'a VisionProvider,
+    ) -> Self {
+        let position = transform.translation().truncate();
+        let fog_position = config.to_fog(position);
+        Self {
+            stamps,
+            environment,
+            heights,
+            vision,
+            world_position: position,
+            position: fog_position,
+            eye_height: heights.height_at(fog_position) + vision.elevation,
+            rotation: provider_rotation(transform),
+            stamp_layer: vision.stamp.as_ref().and_then(|stamp| stamps.layer(stamp)),
+        }
+    }
+
+    /// 视野层上受视野环境影响后的视野范围
+    /// Vision range on vision layers after the vision environment
+    fn range_on(&self, layers: VisionLayers) -> f32 {
+        self.environment
+            .range_for(self.vision.range, self.world_position, layers)
+    }
+
+    /// 视野层上视野能到达的最远距离，印章覆盖一个旋转的正方形，其外接圆半径为 range * √2
+    /// Farthest distance the vision reaches on vision layers; a stamp covers a rotated square whose circumradius is range * √2
+    fn reach_on(&self, layers: VisionLayers) -> f32 {
+        let range = self.range_on(layers);
+        if self.stamp_layer.is_some() {
+            range * SQRT_2
+        } else {
+            range
+        }
+    }
+
+    /// 视野层上迷雾空间中的位置是否未被地形遮挡；地形同时遮挡提供者所在的层和这些层时，提供者看不到更高的台地
+    /// Whether a fog space position on vision layers is not occluded by the terrain; when the terrain occludes both the
+    /// layer the provider lives on and these layers, the provider cannot see onto higher ledges
+    fn overlooks(&self, fog_position: Vec2, layers: VisionLayers) -> bool {
+        !self.heights.occludes(self.vision.lives_on | layers)
+            || self.heights.height_at(fog_position) <= self.eye_height
+    }
+
+    /// 视野层上迷雾空间中的位置是否在视野内，`layers` 为提供者能看到的层
+    /// Whether a fog space position on vision layers is in sight, with `layers` seen by the provider
+    fn sees_within(&self, fog_position: Vec2, layers: VisionLayers) -> bool {
+        if !self.overlooks(fog_position, layers) {
+            return false;
+        }
+        let range = self.range_on(layers);
+        match self.stamp_layer {
+            Some(layer) => {
+                let uv = stamp_uv(fog_position - self.position, self.rotation, range);
+                self.stamps.sample(layer, uv) >= VISION_STAMP_THRESHOLD
+            }
+            None => self.position.distance(fog_position) <= range,
+        }
+    }
+
+    /// 视野层上迷雾空间中的位置是否在视野内
+    /// Whether a fog space position on vision layers is in sight
+    pub(crate) fn sees_on(&self, fog_position: Vec2, layers: VisionLayers) -> bool {
+        let layers = self.vision.layers & layers;
+        layers != VisionLayers::NONE && self.sees_within(fog_position, layers)
+    }
+
+    /// 视野层上迷雾空间中的位置是否在探测范围内
+    /// Whether a fog space position on vision layers is within detection range
+    pub(crate) fn detects(&self, fog_position: Vec2, layers: VisionLayers) -> bool {
+        let layers = self.vision.layers & layers;
+        layers != VisionLayers::NONE
+            && self.overlooks(fog_position, layers)
+            && self.position.distance(fog_position)
+                <= self
+                    .environment
+                    .range_for(self.vision.detection_range, self.world_position, layers)
+    }
+}
+
+/// 视野提供者在迷雾显示的视野层上覆盖的区块
+/// Chunks covered by a vision provider on the vision layers the fog shows
+fn provider_footprint(
+    config: &FogOfWarConfig,
+    environment: &VisionEnvironment,
+    stamps: &VisionStamps,
+    heights: &VisionHeightMap,
+    transform: &GlobalTransform,
+    vision: &VisionProvider,
+) -> ChunkBitSet {
+    let layers = vision.layers & config.vision_layers;
+    if layers == VisionLayers::NONE {
+        return ChunkBitSet::default();
+    }
+    let sight = ProviderSight::new(config, environment, stamps, heights, transform, vision);
+    let chunk_range = ((sight.reach_on(layers) / config.layout.chunk_spacing(config.chunk_size))
+        * 1.5)
+        .ceil() as i32;
+    // 视野范围与区块都在迷雾空间中计算
+    // Vision ranges and chunks are computed in fog space
+    let center_chunk = config.layout.world_to_chunk(sight.position, config.chunk_size);
+
+    config
+        .layout
+        .chunks_in_range(center_chunk, chunk_range)
+        .filter(|&chunk_pos| {
+            sight.sees_within(config.layout.chunk_center(chunk_pos, config.chunk_size), layers)
+        })
+        .collect()
+}
+
+/// 视野提供者查询：实体、变换、视野和所属的迷雾层
+/// Vision provider query: entity, transform, vision and fog layer
+type VisionProviderQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Ref<'static, GlobalTransform>,
+        Ref<'static, VisionProvider>,
+        Option<&'static InFogLayer>,
+    ),
+>;
+
+/// 本帧重新计算的覆盖区块：所属的迷雾层、视野提供者和覆盖区块
+/// Footprints recomputed this frame: fog layer, vision provider and footprint
+type ProviderFootprints = Vec<(Option<Entity>, Entity, ChunkBitSet)>;
+
+/// 更新各迷雾层时共享的数据
+/// Data shared while updating each fog layer
+struct VisionUpdate<'a, 'w, 's> {
+    environment: &'a VisionEnvironment,
+    stamps: &'a VisionStamps,
+    heights: &'a VisionHeightMap,
+    vision_providers: &'a VisionProviderQuery<'w, 's>,
+    /// 本帧移除的视野提供者 (Vision providers removed this frame)
+    removed: &'a [Entity],
+    /// 是否重新计算所有提供者 (Whether to recompute every provider)
+    rebuild: bool,
 }
 
-/// 更新区块可见性
-/// Update chunk visibility
+impl VisionUpdate<'_, '_, '_> {
+    /// 提供者是否属于迷雾层
+    /// Whether a provider belongs to a fog layer
+    fn in_layer(&self, entity: Entity, layer: Option<Entity>) -> bool {
+        self.vision_providers
+            .get(entity)
+            .is_ok_and(|(.., in_layer)| in_layer.map(|in_layer| in_layer.0) == layer)
+    }
+}
+
+/// 用迷雾层中变化的视野提供者的新覆盖区块更新覆盖计数
+/// Update the coverage counts of a fog layer with the new footprints of its changed vision providers
+fn apply_provider_footprints(
+    update: &VisionUpdate,
+    layer: Option<Entity>,
+    rebuild: bool,
+    chunk_manager: &mut FogChunkManager,
+    footprints: Vec<(Entity, ChunkBitSet)>,
+) {
+    // 移除或离开该层的提供者不再覆盖任何区块
+    // Providers removed or gone from the layer no longer cover any chunk
+    let mut removed = update.removed.to_vec();
+    if rebuild {
+        removed.extend(
+            chunk_manager
+                .footprints
+                .keys()
+                .filter(|entity| !update.in_layer(**entity, layer)),
+        );
+    }
+    for entity in removed {
+        if let Some(old) = chunk_manager.footprints.remove(&entity) {
+            for coord in old.iter() {
+                chunk_manager.uncover(coord);
+            }
+        }
+    }
+    for (entity, footprint) in footprints {
+        let old = chunk_manager.footprints.remove(&entity).unwrap_or_default();
+        for coord in old.iter().filter(|coord| !footprint.contains(coord)) {
+            chunk_manager.uncover(coord);
+        }
+        for coord in footprint.iter().filter(|coord| !old.contains(coord)) {
+            chunk_manager.cover(coord);
+        }
+        chunk_manager.footprints.insert(entity, footprint);
+    }
+}
+
+/// 更新等待更新的区块实体，区块可能在同一帧内先离开再进入视野
+/// Update chunk entities awaiting an update; a chunk may leave and re-enter vision within the same frame
+fn update_chunk_entities(
+    layer: Option<Entity>,
+    chunk_manager: &mut FogChunkManager,
+    commands: &mut Commands,
+    chunks: &mut Query<&mut FogChunk>,
+    current_time: f32,
+) {
+    let mut touched = core::mem::take(&mut chunk_manager.touched);
+    for coord in touched.drain(..) {
+        let is_visible = chunk_manager.is_visible(&coord);
+        let visibility = if is_visible {
+            ChunkVisibility::Visible
+        } else if chunk_manager.explored_chunks.contains(&coord) {
+            ChunkVisibility::Explored
+        } else {
+            ChunkVisibility::Unexplored
+        };
+        match chunk_manager.active_chunks.entry(coord) {
+            Entry::Occupied(entry) => {
+                let Ok(mut chunk) = chunks.get_mut(*entry.get()) else {
+                    continue;
+                };
+                if chunk.visibility == visibility {
+                    continue;
+                }
+                if chunk.visibility == ChunkVisibility::Visible {
+                    // 区块从可见变为不可见
+                    // Chunk transitions from visible to not visible
+                    chunk.last_visible_time = current_time;
+                }
+                chunk.visibility = visibility;
+            }
+            Entry::Vacant(entry) if is_visible => {
+                // 创建新区块，迷雾层的区块带有所属的层
+                // Create new chunk; chunks of fog layers carry their layer
+                let mut chunk_entity = commands.spawn((
+                    coord,
+                    FogChunk {
+                        visibility: ChunkVisibility::Visible,
+                        last_visible_time: current_time,
+                    },
+                ));
+                if let Some(layer) = layer {
+                    chunk_entity.insert(InFogLayer(layer));
+                }
+                entry.insert(chunk_entity.id());
+            }
+            Entry::Vacant(_) => {}
+        }
+    }
+    // 保留已分配的容量
+    // Keep the allocated capacity
+    chunk_manager.touched = touched;
+}
+
+/// 增量更新默认层和所有迷雾层的区块可见性
+/// Incrementally update chunk visibility of the default layer and every fog layer
+///
+/// 只重新计算移动、修改或移除的视野提供者的覆盖区块，并只处理进入或离开覆盖范围的区块；
+/// 配置、视野环境、印章、高度图或提供者所属的层变化时重新计算所有提供者
+/// Only the footprints of moved, modified or removed vision providers are recomputed, and only chunks
+/// entering or leaving a footprint are touched; all providers are recomputed when the config,
+/// vision environment, stamps, height map or provider layers change
+#[allow(clippy::too_many_arguments)]
 pub fn update_chunk_visibility(
     time: Res<Time>,
     mut commands: Commands,
     config: Res<FogOfWarConfig>,
+    environment: Res<VisionEnvironment>,
+    stamps: Res<VisionStamps>,
+    heights: Res<VisionHeightMap>,
     mut chunk_manager: ResMut<FogChunkManager>,
-    vision_providers: Query<(&GlobalTransform, &VisionProvider)>,
-    mut chunks: Query<(Entity, &ChunkCoord, &mut FogChunk)>,
+    mut layers: Query<(Entity, Ref<FogOfWarConfig>, &mut FogChunkManager), With<FogLayer>>,
+    vision_providers: VisionProviderQuery,
+    changed_membership: Query<(), (Changed<InFogLayer>, With<VisionProvider>)>,
+    mut removed_providers: RemovedComponents<VisionProvider>,
+    mut removed_membership: RemovedComponents<InFogLayer>,
+    mut chunks: Query<&mut FogChunk>,
+    mut footprints: Local<Parallel<ProviderFootprints>>,
 ) {
-    // 计算当前可见的区块
-    // Calculate currently visible chunks
-    let mut new_visible_chunks = HashSet::new();
-    
-    for (transform, vision) in vision_providers.iter() {
-        let position = transform.translation().truncate();
-        let chunk_range = ((vision.range / config.chunk_size) * 1.5).ceil() as i32;
-        
-        // 计算视野提供者可见的区块
-        // Calculate chunks visible to the vision provider
-        for x in -chunk_range..=chunk_range {
-            for y in -chunk_range..=chunk_range {
-                let center = Vec2::new(position.x, position.y);
-                let chunk_pos = ChunkCoord {
-                    x: (position.x / config.chunk_size).floor() as i32 + x,
-                    y: (position.y / config.chunk_size).floor() as i32 + y,
-                };
-                
-                let chunk_center = Vec2::new(
-                    (chunk_pos.x as f32 + 0.5) * config.chunk_size,
-                    (chunk_pos.y as f32 + 0.5) * config.chunk_size,
-                );
-                
-                let distance = center.distance(chunk_center);
-                if distance <= vision.range {
-                    new_visible_chunks.insert(chunk_pos);
-                    chunk_manager.explored_chunks.insert(chunk_pos);
-                }
-            }
+    let current_time = time.elapsed_secs();
+    let removed: Vec<Entity> = removed_providers.read().collect();
+    // 区块实体同样带有 InFogLayer，只关心仍是视野提供者的实体；读完所有移除以免留到下一帧
+    // Chunk entities carry InFogLayer as well, so only entities that are still vision providers matter;
+    // every removal is read so none is left for the next frame
+    let membership_removed = removed_membership
+        .read()
+        .filter(|entity| vision_providers.contains(*entity))
+        .count();
+    let membership_changed = !changed_membership.is_empty() || membership_removed > 0;
+    let update = VisionUpdate {
+        environment: &environment,
+        stamps: &stamps,
+        heights: &heights,
+        vision_providers: &vision_providers,
+        removed: &removed,
+        rebuild: environment.is_changed()
+            || stamps.is_changed()
+            || heights.is_changed()
+            || membership_changed,
+    };
+
+    let mut layer_items: Vec<_> = layers.iter_mut().collect();
+    let mut states = Vec::with_capacity(layer_items.len() + 1);
+    states.push((None, &*config, config.is_changed(), &mut *chunk_manager));
+    for (layer, layer_config, layer_manager) in &mut layer_items {
+        states.push((
+            Some(*layer),
+            &**layer_config,
+            layer_config.is_changed(),
+            &mut **layer_manager,
+        ));
+    }
+
+    // 冻结时忽略视野提供者的变化，解冻后重新计算所有提供者
+    // Vision provider changes are ignored while frozen, and all providers are recomputed once thawed
+    let mut active: HashMap<Option<Entity>, (&FogOfWarConfig, bool)> = HashMap::new();
+    for (layer, config, config_changed, chunk_manager) in &mut states {
+        chunk_manager.expire_reveals(current_time);
+        let thawed = core::mem::replace(&mut chunk_manager.was_frozen, chunk_manager.frozen)
+            && !chunk_manager.frozen;
+        if !chunk_manager.frozen {
+            let stale = core::mem::take(&mut chunk_manager.footprints_stale);
+            let rebuild = *config_changed || thawed || stale || update.rebuild;
+            active.insert(*layer, (*config, rebuild));
         }
     }
-    
-    // 更新区块可见性状态
-    // Update chunk visibility states
-    let current_time = time.elapsed_secs();
-    
-    // 处理不再可见的区块
-    // Handle chunks that are no longer visible
-    for (entity, coord, mut chunk) in chunks.iter_mut() {
-        let is_visible = new_visible_chunks.contains(coord);
-        
-        match (chunk.visibility, is_visible) {
-            (ChunkVisibility::Visible, false) => {
-                // 区块从可见变为不可见
-                // Chunk transitions from visible to not visible
-                chunk.visibility = ChunkVisibility::Explored;
-                chunk.last_visible_time = current_time;
-            }
-            (ChunkVisibility::Unexplored, true) | (ChunkVisibility::Explored, true) => {
-                // 区块变为可见
-                // Chunk becomes visible
-                chunk.visibility = ChunkVisibility::Visible;
-            }
-            _ => {}
-        }
-    }
-    
-    // 更新可见区块集合
-    // Update visible chunks collection
-    chunk_manager.visible_chunks = new_visible_chunks;
-    
-    // 克隆集合以避免借用冲突
-    // Clone collections to avoid borrowing conflicts
-    let visible_chunks = chunk_manager.visible_chunks.clone();
-    let explored_chunks = chunk_manager.explored_chunks.clone();
-    
-    // 创建新的区块实体
-    // Create new chunk entities
-    for coord in visible_chunks.iter().chain(explored_chunks.iter()) {
-        if !chunk_manager.active_chunks.contains_key(coord) {
-            // 创建新区块
-            // Create new chunk
-            let visibility = if visible_chunks.contains(coord) {
-                ChunkVisibility::Visible
-            } else {
-                ChunkVisibility::Explored
+
+    // 一次遍历所有提供者，在计算任务池上并行计算各层中变化的提供者的覆盖区块
+    // Walk every provider once, computing the footprints of changed providers of each layer in parallel on the compute task pool
+    let queue = &*footprints;
+    vision_providers
+        .par_iter()
+        .for_each(|(entity, transform, vision, in_layer)| {
+            let layer = in_layer.map(|in_layer| in_layer.0);
+            let Some(&(config, rebuild)) = active.get(&layer) else {
+                return;
             };
-            
-            let chunk_entity = commands.spawn((
-                *coord,
-                FogChunk {
-                    visibility,
-                    last_visible_time: if visibility == ChunkVisibility::Visible {
-                        current_time
-                    } else {
-                        0.0
-                    },
-                },
-            )).id();
-            
-            chunk_manager.active_chunks.insert(*coord, chunk_entity);
+            if rebuild || transform.is_changed() || vision.is_changed() {
+                let footprint = provider_footprint(
+                    config,
+                    update.environment,
+                    update.stamps,
+                    update.heights,
+                    &transform,
+                    &vision,
+                );
+                queue.borrow_local_mut().push((layer, entity, footprint));
+            }
+        });
+    let mut changed: HashMap<Option<Entity>, Vec<(Entity, ChunkBitSet)>> = HashMap::new();
+    for (layer, entity, footprint) in footprints.drain() {
+        changed.entry(layer).or_default().push((entity, footprint));
+    }
+
+    for (layer, _, _, chunk_manager) in states {
+        if let Some(&(_, rebuild)) = active.get(&layer) {
+            let footprints = changed.remove(&layer).unwrap_or_default();
+            apply_provider_footprints(&update, layer, rebuild, chunk_manager, footprints);
         }
+        update_chunk_entities(layer, chunk_manager, &mut commands, &mut chunks, current_time);
     }
 }
 
-/// 加载和卸载区块
-/// Load and unload chunks
+/// 卸载默认层和所有迷雾层中远离相机的区块
+/// Unload chunks far from the cameras of the default layer and every fog layer
+///
+/// 每个迷雾层只考虑渲染该层的相机，没有相机时不卸载
+/// Each layer only considers the cameras rendering it, and nothing is unloaded without one
 pub fn manage_chunks(
     time: Res<Time>,
     mut commands: Commands,
     config: Res<FogOfWarConfig>,
     mut chunk_manager: ResMut<FogChunkManager>,
-    camera_query: Query<&GlobalTransform, With<Camera>>,
-    chunks: Query<(Entity, &ChunkCoord, &FogChunk)>,
+    mut layers: Query<(Entity, &FogOfWarConfig, &mut FogChunkManager), With<FogLayer>>,
+    camera_query: Query<(&GlobalTransform, Option<&FogMaterial>), With<Camera>>,
+    chunks: Query<&FogChunk>,
 ) {
-    // 获取相机位置
-    // Get camera position
-    let camera_position = if let Ok(transform) = camera_query.get_single() {
-        transform.translation().truncate()
+    let current_time = time.elapsed_secs();
+    let mut unload_chunks = |layer: Option<Entity>,
+                             config: &FogOfWarConfig,
+                             chunk_manager: &mut FogChunkManager| {
+        // 计算渲染该层的相机所在区块
+        // Calculate the chunks of the cameras rendering this layer
+        let camera_chunks: Vec<ChunkCoord> = camera_query
+            .iter()
+            .filter(|(_, fog_material)| {
+                fog_material.and_then(|fog_material| fog_material.layer) == layer
+            })
+            .map(|(transform, _)| config.world_to_chunk(transform.translation().truncate()))
+            .collect();
+        if camera_chunks.is_empty() {
+            return;
+        }
+
+        // 计算加载范围（比视野范围大一些）
+        // Calculate loading range (slightly larger than view range)
+        let load_range = config.view_range + 2;
+
+        // 卸载远离相机的区块
+        // Unload chunks far from camera
+        chunk_manager.active_chunks.retain(|coord, entity| {
+            // 本帧创建的区块尚未生成
+            // Chunks created this frame are not spawned yet
+            let Ok(chunk) = chunks.get(*entity) else {
+                return true;
+            };
+            let distance = camera_chunks
+                .iter()
+                .map(|camera_chunk| config.layout.chunk_distance(*coord, *camera_chunk))
+                .fold(f32::INFINITY, f32::min);
+
+            // 如果区块太远且不可见，考虑卸载
+            // If chunk is too far and not visible, consider unloading
+            let unload = distance > load_range as f32
+                && match chunk.visibility {
+                    // 如果是已探索区块，只有在一定时间后才卸载
+                    // If it's an explored chunk, only unload after some time
+                    ChunkVisibility::Explored => {
+                        current_time - chunk.last_visible_time > 60.0 // 1分钟后卸载 / Unload after 1 minute
+                    }
+                    // 未探索区块可以立即卸载
+                    // Unexplored chunks can be unloaded immediately
+                    ChunkVisibility::Unexplored => true,
+                    ChunkVisibility::Visible => false,
+                };
+
+            // 保留在已探索集合中，这样我们仍然知道它已被探索
+            // Keep in explored set so we still know it was explored
+            if unload {
+                commands.entity(*entity).despawn();
+            }
+            !unload
+        });
+    };
+
+    unload_chunks(None, &config, &mut chunk_manager);
+    for (layer, layer_config, mut layer_manager) in &mut layers {
+        unload_chunks(Some(layer), layer_config, &mut layer_manager);
+    }
+}
+
+/// 设备限制不可用时已探索纹理的最大边长，即 wgpu 保证的 `max_texture_dimension_2d` 下限
+/// Maximum side of the explored texture when device limits are unavailable, the `max_texture_dimension_2d` wgpu guarantees
+const FALLBACK_EXPLORED_TEXTURE_SIZE: u32 = 2048;
+
+/// 准备默认层和所有迷雾层的渲染数据
+/// Prepare rendering data of the default layer and every fog layer
+pub fn prepare_fog_data(
+    config: Res<FogOfWarConfig>,
+    mut chunk_manager: ResMut<FogChunkManager>,
+    mut explored_texture: ResMut<FogExploredTexture>,
+    mut layers: Query<
+        (&FogOfWarConfig, &mut FogChunkManager, &mut FogExploredTexture),
+        With<FogLayer>,
+    >,
+    render_device: Option<Res<RenderDevice>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let max_size = render_device.map_or(FALLBACK_EXPLORED_TEXTURE_SIZE, |device| {
+        device.limits().max_texture_dimension_2d
+    });
+    update_explored_texture(
+        &config,
+        &mut chunk_manager,
+        &mut explored_texture,
+        max_size,
+        &mut images,
+    );
+    for (config, mut chunk_manager, mut explored_texture) in &mut layers {
+        update_explored_texture(
+            config,
+            &mut chunk_manager,
+            &mut explored_texture,
+            max_size,
+            &mut images,
+        );
+    }
+}
+
+/// 将已探索和揭示状态的变化写入 [`FogExploredTexture`]，只在纹理窗口需要移动或迷雾状态被替换时重建
+/// Write explored and revealed state changes into the [`FogExploredTexture`], rebuilding only when the texture window
+/// has to move or the fog state was replaced
+fn update_explored_texture(
+    config: &FogOfWarConfig,
+    chunk_manager: &mut FogChunkManager,
+    explored_texture: &mut FogExploredTexture,
+    max_size: u32,
+    images: &mut Assets<Image>,
+) {
+    explored_texture.reveal_all = chunk_manager.reveal_all;
+    let dirty = core::mem::take(&mut chunk_manager.texture_dirty);
+    let explored = &chunk_manager.explored_chunks;
+
+    let replaced = explored_texture.revision != chunk_manager.revision
+        || explored_texture.layout != config.layout
+        || explored_texture.chunk_size != config.chunk_size
+        || explored_texture.world_to_fog != config.world_to_fog;
+    if replaced {
+        explored_texture.revision = chunk_manager.revision;
+        explored_texture.layout = config.layout;
+        explored_texture.chunk_size = config.chunk_size;
+        explored_texture.world_to_fog = config.world_to_fog;
+        explored_texture.bounds = None;
+        extend_bounds(&mut explored_texture.bounds, explored.iter());
     } else {
+        extend_bounds(
+            &mut explored_texture.bounds,
+            dirty.iter().copied().filter(|coord| explored.contains(coord)),
+        );
+    }
+    let Some((min, max)) = explored_texture.bounds else {
+        explored_texture.image = None;
         return;
     };
-    
-    // 计算相机所在区块
-    // Calculate camera chunk
-    let camera_chunk = ChunkCoord {
-        x: (camera_position.x / config.chunk_size).floor() as i32,
-        y: (camera_position.y / config.chunk_size).floor() as i32,
+
+    // 四周留出一个未探索区块，使线性采样在边界处过渡到未探索
+    // Leave one unexplored chunk around the edges so linear sampling fades to unexplored at the border
+    let (min, max) = (min - IVec2::ONE, max + IVec2::ONE);
+    let limit = max_size as i32;
+    let centre = if (max - min).cmpge(IVec2::splat(limit)).any() {
+        visible_centre(&chunk_manager.visible_chunks).unwrap_or((min + max) / 2)
+    } else {
+        (min + max) / 2
     };
-    
-    // 计算加载范围（比视野范围大一些）
-    // Calculate loading range (slightly larger than view range)
-    let load_range = config.view_range + 2;
-    
-    // 卸载远离相机的区块
-    // Unload chunks far from camera
-    let current_time = time.elapsed_secs();
-    let mut chunks_to_remove = Vec::new();
-    
-    for (entity, coord, chunk) in chunks.iter() {
-        let dx = (coord.x - camera_chunk.x).abs();
-        let dy = (coord.y - camera_chunk.y).abs();
-        let distance = (dx * dx + dy * dy) as f32;
-        
-        // 如果区块太远且不可见，考虑卸载
-        // If chunk is too far and not visible, consider unloading
-        if distance > (load_range * load_range) as f32 {
-            // 如果是已探索区块，只有在一定时间后才卸载
-            // If it's an explored chunk, only unload after some time
-            if chunk.visibility == ChunkVisibility::Explored {
-                let time_since_visible = current_time - chunk.last_visible_time;
-                if time_since_visible > 60.0 { // 1分钟后卸载 / Unload after 1 minute
-                    chunks_to_remove.push((*coord, entity));
-                }
-            } else if chunk.visibility == ChunkVisibility::Unexplored {
-                // 未探索区块可以立即卸载
-                // Unexplored chunks can be unloaded immediately
-                chunks_to_remove.push((*coord, entity));
+    let current_origin = IVec2::new(explored_texture.origin.x, explored_texture.origin.y);
+    let current_size = explored_texture.size.as_ivec2();
+    let (origin_x, size_x) =
+        explored_window_axis(min.x, max.x, centre.x, limit, (current_origin.x, current_size.x));
+    let (origin_y, size_y) =
+        explored_window_axis(min.y, max.y, centre.y, limit, (current_origin.y, current_size.y));
+    let origin = IVec2::new(origin_x, origin_y);
+    let size = IVec2::new(size_x, size_y);
+
+    // 窗口不变时只写入变化的区块
+    // While the window stays put only the changed chunks are written
+    let reusable = explored_texture
+        .image
+        .as_ref()
+        .filter(|_| !replaced && origin == current_origin && size == current_size);
+    if let Some(handle) = reusable {
+        let dirty: Vec<_> = dirty
+            .into_iter()
+            .filter(|coord| in_window(coord, origin, size))
+            .collect();
+        if dirty.is_empty() {
+            return;
+        }
+        if let Some(image) = images.get_mut(handle) {
+            for coord in dirty {
+                write_explored_pixel(&mut image.data, chunk_manager, coord, origin, size);
             }
+            return;
         }
     }
-    
-    // 执行卸载
-    // Perform unloading
-    for (coord, entity) in chunks_to_remove {
-        commands.entity(entity).despawn();
-        chunk_manager.active_chunks.remove(&coord);
-        // 保留在已探索集合中，这样我们仍然知道它已被探索
-        // Keep in explored set so we still know it was explored
+
+    let mut data = vec![0; (size.x * size.y * 2) as usize];
+    let revealed = chunk_manager
+        .reveals
+        .iter()
+        .flat_map(|reveal| reveal.chunks.iter());
+    for coord in explored.iter().chain(revealed) {
+        if in_window(&coord, origin, size) {
+            write_explored_pixel(&mut data, chunk_manager, coord, origin, size);
+        }
+    }
+    let image = Image::new(
+        Extent3d {
+            width: size.x as u32,
+            height: size.y as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rg8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+
+    match &explored_texture.image {
+        Some(handle) => images.insert(handle, image),
+        None => explored_texture.image = Some(images.add(image)),
     }
+    explored_texture.origin = ChunkCoord {
+        x: origin.x,
+        y: origin.y,
+    };
+    explored_texture.size = size.as_uvec2();
 }
 
-/// 准备迷雾渲染数据
-/// Prepare fog rendering data
-pub fn prepare_fog_data(
-    chunk_manager: Res<FogChunkManager>,
-    chunks: Query<(&ChunkCoord, &FogChunk)>,
+/// 用区块扩展已探索范围
+/// Extend the explored bounds by chunks
+fn extend_bounds(
+    bounds: &mut Option<(IVec2, IVec2)>,
+    chunks: impl IntoIterator<Item = ChunkCoord>,
+) {
+    for coord in chunks {
+        let coord = IVec2::new(coord.x, coord.y);
+        *bounds = Some(bounds.map_or((coord, coord), |(min, max)| {
+            (min.min(coord), max.max(coord))
+        }));
+    }
+}
+
+/// 可见区块范围的中心
+/// Centre of the bounds of the visible chunks
+fn visible_centre(visible: &ChunkBitSet) -> Option<IVec2> {
+    let mut bounds = None;
+    extend_bounds(&mut bounds, visible.iter());
+    bounds.map(|(min, max)| (min + max) / 2)
+}
+
+/// 选择已探索纹理窗口在一个轴上的起点和长度，当前窗口仍可用时保持不变
+/// Pick the origin and length of the explored texture window along one axis, keeping the current window while it still fits
+///
+/// 范围不超过 `limit` 时窗口两侧各留出一半范围的增长余量；否则窗口以 `centre` 为中心，
+/// 只有中心移动超过四分之一窗口时才移动，避免每帧重建
+/// When the range fits within `limit` the window leaves half the range of room to grow on each side; otherwise it is
+/// centred on `centre` and only moves once the centre drifts more than a quarter window, so it is not rebuilt every frame
+fn explored_window_axis(
+    min: i32,
+    max: i32,
+    centre: i32,
+    limit: i32,
+    current: (i32, i32),
+) -> (i32, i32) {
+    let (origin, size) = current;
+    let span = max - min + 1;
+    if span <= limit {
+        if origin <= min && max < origin + size {
+            return current;
+        }
+        let padded = (span + 2 * (span / 2).max(8)).min(limit);
+        (min - (padded - span) / 2, padded)
+    } else {
+        let target = centre - limit / 2;
+        if size == limit && (target - origin).abs() < limit / 4 {
+            return current;
+        }
+        (target, limit)
+    }
+}
+
+/// 区块是否位于纹理窗口内
+/// Whether a chunk lies within the texture window
+fn in_window(coord: &ChunkCoord, origin: IVec2, size: IVec2) -> bool {
+    let texel = IVec2::new(coord.x, coord.y) - origin;
+    texel.cmpge(IVec2::ZERO).all() && texel.cmplt(size).all()
+}
+
+/// 写入区块在已探索纹理中的像素，纹理第一行位于 y 最大处
+/// Write the pixel of a chunk in the explored texture, whose first row is at the greatest y
+fn write_explored_pixel(
+    data: &mut [u8],
+    chunk_manager: &FogChunkManager,
+    coord: ChunkCoord,
+    origin: IVec2,
+    size: IVec2,
 ) {
-    // 这里可以添加与渲染系统的集成代码
-    // Here you can add integration code with the rendering system
-    // 例如创建或更新用于渲染的纹理
-    // For example, create or update textures for rendering
+    let texel = IVec2::new(coord.x, coord.y) - origin;
+    let index = (((size.y - 1 - texel.y) * size.x + texel.x) * 2) as usize;
+    let revealed = chunk_manager
+        .reveals
+        .iter()
+        .any(|reveal| reveal.chunks.contains(&coord));
+    data[index] = if chunk_manager.explored_chunks.contains(&coord) { 255 } else { 0 };
+    data[index + 1] = if revealed { 255 } else { 0 };
 }
 
 /// 战争迷雾区块系统插件
@@ -245,7 +1203,9 @@ impl Plugin for FogChunkPlugin {
     fn build(&self, app: &mut App) {
         app
             .init_resource::<FogChunkManager>()
+            .init_resource::<FogExploredTexture>()
             .register_type::<ChunkCoord>()
+            .register_type::<ChunkLayout>()
             .register_type::<ChunkVisibility>()
             .register_type::<FogChunk>()
             // .register_type::<VisionProvider>()
@@ -256,3 +1216,132 @@ impl Plugin for FogChunkPlugin {
             ).chain());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUTS: [ChunkLayout; 3] = [
+        ChunkLayout::Square,
+        ChunkLayout::HexPointy,
+        ChunkLayout::HexFlat,
+    ];
+
+    #[test]
+    fn hex_round_picks_the_nearest_hex() {
+        assert_eq!(hex_round(Vec2::new(0.2, 0.2)), ChunkCoord { x: 0, y: 0 });
+        assert_eq!(hex_round(Vec2::new(0.6, 0.1)), ChunkCoord { x: 1, y: 0 });
+        assert_eq!(hex_round(Vec2::new(-0.6, -0.1)), ChunkCoord { x: -1, y: 0 });
+        assert_eq!(hex_round(Vec2::new(0.45, -0.45)), ChunkCoord { x: 0, y: 0 });
+        assert_eq!(
+            hex_round(Vec2::new(0.55, -0.55)),
+            ChunkCoord { x: 1, y: -1 }
+        );
+        assert_eq!(hex_round(Vec2::new(-2.9, 1.95)), ChunkCoord { x: -3, y: 2 });
+    }
+
+    #[test]
+    fn chunk_centers_round_trip() {
+        for layout in LAYOUTS {
+            for x in -20..=20 {
+                for y in -20..=20 {
+                    let coord = ChunkCoord { x, y };
+                    let center = layout.chunk_center(coord, 32.0);
+                    assert_eq!(layout.world_to_chunk(center, 32.0), coord, "{layout:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn positions_near_a_center_stay_in_its_chunk() {
+        // 小于内切圆半径的偏移不会离开区块
+        // Offsets smaller than the inner radius never leave the chunk
+        let chunk_size = 10.0;
+        for layout in LAYOUTS {
+            let inner_radius = 0.49 * layout.chunk_spacing(chunk_size);
+            for x in -6..=6 {
+                for y in -6..=6 {
+                    let coord = ChunkCoord { x, y };
+                    let center = layout.chunk_center(coord, chunk_size);
+                    for step in 0..12 {
+                        let angle = step as f32 * core::f32::consts::TAU / 12.0;
+                        let offset = Vec2::from_angle(angle) * inner_radius;
+                        assert_eq!(
+                            layout.world_to_chunk(center + offset, chunk_size),
+                            coord,
+                            "{layout:?} {angle}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn square_chunks_floor_negative_positions() {
+        let layout = ChunkLayout::Square;
+        assert_eq!(
+            layout.world_to_chunk(Vec2::new(-0.1, -0.1), 10.0),
+            ChunkCoord { x: -1, y: -1 }
+        );
+        assert_eq!(
+            layout.world_to_chunk(Vec2::new(-10.0, 9.9), 10.0),
+            ChunkCoord { x: -1, y: 0 }
+        );
+        assert_eq!(
+            layout.world_to_chunk(Vec2::new(-10.1, 10.0), 10.0),
+            ChunkCoord { x: -2, y: 1 }
+        );
+    }
+
+    #[test]
+    fn air_providers_see_onto_higher_ledges() {
+        let config = FogOfWarConfig::default();
+        let environment = VisionEnvironment::default();
+        let stamps = VisionStamps::default();
+        let mut heights = VisionHeightMap::new(Vec2::ZERO, 10.0, UVec2::new(4, 1));
+        heights.set(UVec2::new(2, 0), 5.0);
+        let transform = GlobalTransform::from_translation(Vec3::new(5.0, 5.0, 0.0));
+        let ledge = Vec2::new(25.0, 5.0);
+
+        let ground = VisionProvider::default();
+        let sight = ProviderSight::new(
+            &config,
+            &environment,
+            &stamps,
+            &heights,
+            &transform,
+            &ground,
+        );
+        assert!(!sight.sees_on(ledge, VisionLayers::GROUND));
+        assert!(sight.sees_on(ledge, VisionLayers::AIR));
+
+        let air = VisionProvider {
+            lives_on: VisionLayers::AIR,
+            ..default()
+        };
+        let sight = ProviderSight::new(
+            &config,
+            &environment,
+            &stamps,
+            &heights,
+            &transform,
+            &air,
+        );
+        assert!(sight.sees_on(ledge, VisionLayers::GROUND));
+    }
+
+    #[test]
+    fn hex_neighbours_are_one_step_away() {
+        for layout in [ChunkLayout::HexPointy, ChunkLayout::HexFlat] {
+            let center = ChunkCoord { x: -3, y: 5 };
+            let center_position = layout.chunk_center(center, 8.0);
+            for neighbour in layout.neighbours(center) {
+                assert_eq!(layout.chunk_distance(center, neighbour), 1.0);
+                let distance = center_position.distance(layout.chunk_center(neighbour, 8.0));
+                assert!((distance - layout.chunk_spacing(8.0)).abs() < 1e-3);
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::bitset::ChunkBitSet;
+use crate::chunk::{ChunkCoord, FogChunkManager};
+use crate::fog::FogOfWarConfig;
+use crate::layer::FogLayer;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+/// 迷雾命令作用的世界空间区域
+/// World-space area a fog command acts on
+///
+/// 中心位于区域内的区块受命令影响
+/// Chunks whose center lies inside the area are affected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FogShape {
+    /// 圆形区域 (Circular area)
+    Circle { center: Vec2, radius: f32 },
+    /// 矩形区域 (Rectangular area)
+    Rect(Rect),
+}
+
+impl FogShape {
+    /// 世界坐标是否在区域内
+    /// Whether a world position lies inside the area
+    pub fn contains(&self, position: Vec2) -> bool {
+        match *self {
+            Self::Circle { center, radius } => center.distance_squared(position) <= radius * radius,
+            Self::Rect(rect) => rect.contains(position),
+        }
+    }
+
+    /// 区域的世界空间包围盒
+    /// World-space bounding box of the area
+    pub fn bounds(&self) -> Rect {
+        match *self {
+            Self::Circle { center, radius } => {
+                Rect::from_center_half_size(center, Vec2::splat(radius))
+            }
+            Self::Rect(rect) => rect,
+        }
+    }
+
+    /// 中心位于区域内的区块
+    /// Chunks whose center lies inside the area
+    pub fn chunks(&self, config: &FogOfWarConfig) -> ChunkBitSet {
+        // 区块坐标是迷雾空间的线性变换，包围盒四角的区块即可界定范围
+        // Chunk coordinates are a linear transform of fog space, so the chunks at the bounding box corners bound the range
+        let bounds = self.bounds();
+        let (min, max) = [
+            bounds.min,
+            Vec2::new(bounds.max.x, bounds.min.y),
+            bounds.max,
+            Vec2::new(bounds.min.x, bounds.max.y),
+        ]
+        .into_iter()
+        .map(|corner| {
+            let coord = config.world_to_chunk(corner);
+            IVec2::new(coord.x, coord.y)
+        })
+        .fold((IVec2::MAX, IVec2::MIN), |(min, max), coord| {
+            (min.min(coord), max.max(coord))
+        });
+
+        let mut chunks = ChunkBitSet::new();
+        for x in min.x - 1..=max.x + 1 {
+            for y in min.y - 1..=max.y + 1 {
+                let coord = ChunkCoord { x, y };
+                if self.contains(config.chunk_center(coord)) {
+                    chunks.insert(coord);
+                }
+            }
+        }
+        chunks
+    }
+}
+
+/// 迷雾命令，用于过场动画、作弊、揭示地图的道具和关卡重置
+/// Fog commands for cutscenes, cheats, map-reveal items and level resets
+///
+/// 命令立即修改 [`FogChunkManager`]，区块实体和 GPU 纹理在本帧的区块系统中更新
+/// Commands change the [`FogChunkManager`] immediately; chunk entities and GPU textures are updated by this frame's chunk systems
+///
+/// 直接调用的命令作用于默认层，[`FogCommands::layer`] 返回作用于 [`FogLayer`] 实体的命令
+/// Commands called directly act on the default layer, and [`FogCommands::layer`] returns commands acting on a [`FogLayer`] entity
+#[derive(SystemParam)]
+pub struct FogCommands<'w, 's> {
+    config: Res<'w, FogOfWarConfig>,
+    time: Res<'w, Time>,
+    chunk_manager: ResMut<'w, FogChunkManager>,
+    layers: Query<
+        'w,
+        's,
+        (&'static FogOfWarConfig, &'static mut FogChunkManager),
+        With<FogLayer>,
+    >,
+}
+
+impl FogCommands<'_, '_> {
+    /// 作用于迷雾层的命令，实体不是迷雾层时返回 `None`
+    /// Commands acting on a fog layer, `None` when the entity is not a fog layer
+    pub fn layer(&mut self, layer: Entity) -> Option<FogLayerCommands<'_>> {
+        let now = self.time.elapsed_secs();
+        let (config, chunk_manager) = self.layers.get_mut(layer).ok()?;
+        Some(FogLayerCommands {
+            config,
+            now,
+            chunk_manager,
+        })
+    }
+
+    /// 作用于默认层的命令
+    /// Commands acting on the default layer
+    fn default_layer(&mut self) -> FogLayerCommands<'_> {
+        FogLayerCommands {
+            config: &self.config,
+            now: self.time.elapsed_secs(),
+            chunk_manager: self.chunk_manager.reborrow(),
+        }
+    }
+
+    /// 在默认层揭示区域，见 [`FogLayerCommands::reveal_area`]
+    /// Reveal an area of the default layer, see [`FogLayerCommands::reveal_area`]
+    pub fn reveal_area(&mut self, shape: FogShape, duration: Option<f32>) {
+        self.default_layer().reveal_area(shape, duration);
+    }
+
+    /// 将默认层的区域标记为已探索
+    /// Mark an area of the default layer as explored
+    pub fn explore_area(&mut self, shape: FogShape) {
+        self.default_layer().explore_area(shape);
+    }
+
+    /// 隐藏默认层的区域，见 [`FogLayerCommands::conceal_area`]
+    /// Conceal an area of the default layer, see [`FogLayerCommands::conceal_area`]
+    pub fn conceal_area(&mut self, shape: FogShape) {
+        self.default_layer().conceal_area(shape);
+    }
+
+    /// 揭示默认层的整张地图，直到迷雾被重置
+    /// Reveal the whole map of the default layer until the fog is reset
+    pub fn reveal_all(&mut self) {
+        self.default_layer().reveal_all();
+    }
+
+    /// 重置默认层的迷雾，见 [`FogLayerCommands::reset_fog`]
+    /// Reset the fog of the default layer, see [`FogLayerCommands::reset_fog`]
+    pub fn reset_fog(&mut self) {
+        self.default_layer().reset_fog();
+    }
+
+    /// 冻结或解冻默认层的迷雾，见 [`FogLayerCommands::freeze_fog`]
+    /// Freeze or unfreeze the fog of the default layer, see [`FogLayerCommands::freeze_fog`]
+    pub fn freeze_fog(&mut self, frozen: bool) {
+        self.default_layer().freeze_fog(frozen);
+    }
+}
+
+/// 作用于单个迷雾层的命令，由 [`FogCommands`] 取得
+/// Fog commands acting on a single fog layer, obtained from [`FogCommands`]
+pub struct FogLayerCommands<'a> {
+    config: &'a FogOfWarConfig,
+    now: f32,
+    chunk_manager: Mut<'a, FogChunkManager>,
+}
+
+impl FogLayerCommands<'_> {
+    /// 揭示区域，`duration` 秒后结束；为 `None` 时一直揭示，直到被隐藏或重置
+    /// Reveal an area, ending after `duration` seconds; when `None` it stays revealed until concealed or reset
+    pub fn reveal_area(&mut self, shape: FogShape, duration: Option<f32>) {
+        let expires_at = duration.map(|duration| self.now + duration);
+        let chunks = shape.chunks(self.config);
+        self.chunk_manager.reveal(chunks, expires_at);
+    }
+
+    /// 将区域标记为已探索
+    /// Mark an area as explored
+    pub fn explore_area(&mut self, shape: FogShape) {
+        let chunks = shape.chunks(self.config);
+        self.chunk_manager.explore(chunks.iter());
+    }
+
+    /// 隐藏区域：结束其中的揭示，并将视野外的区块恢复为未探索
+    /// Conceal an area: end reveals within it and turn chunks outside vision back to unexplored
+    pub fn conceal_area(&mut self, shape: FogShape) {
+        let chunks = shape.chunks(self.config);
+        self.chunk_manager.conceal(&chunks);
+    }
+
+    /// 揭示整张地图，直到迷雾被重置
+    /// Reveal the whole map until the fog is reset
+    pub fn reveal_all(&mut self) {
+        self.chunk_manager.set_reveal_all(true);
+    }
+
+    /// 重置迷雾：结束所有揭示，清除视野外的已探索区块
+    /// Reset the fog: end all reveals and clear explored chunks outside vision
+    pub fn reset_fog(&mut self) {
+        self.chunk_manager.reset();
+    }
+
+    /// 冻结或解冻迷雾，冻结时视野提供者的移动和变化不再影响迷雾
+    /// Freeze or unfreeze the fog; while frozen, vision providers moving or changing no longer affect it
+    pub fn freeze_fog(&mut self, frozen: bool) {
+        self.chunk_manager.set_frozen(frozen);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::vision_layers::VisionLayers;
+use bevy::prelude::*;
+
+/// 视野环境，用于昼夜循环和天气
+/// Vision environment, used for day/night cycles and weather
+///
+/// 视野提供者的有效范围 = `range` × 全局倍率 × 提供者所在的所有区域的倍率
+/// Effective provider range = `range` × global multiplier × multipliers of every zone the provider stands in
+///
+/// 区域只缩放站在其中的提供者的范围，例如雨区或浓雾，不会遮挡区域外提供者的视线；
+/// 遮挡视线的地形请使用 [`VisionHeightMap`](crate::prelude::VisionHeightMap)
+/// Zones only scale the range of providers standing inside them, e.g. rain or thick mist, and do not block the line
+/// of sight of providers outside; use the [`VisionHeightMap`](crate::prelude::VisionHeightMap) for terrain that blocks sight
+///
+/// 区域只影响其 [`VisionZone::layers`] 中的视野层。对目标的判断使用目标所在的层，因此只影响地面的区域会缩短
+/// 默认提供者对地面目标的视野；区块视野使用提供者能看到的层与
+/// [`FogOfWarConfig::vision_layers`](crate::prelude::FogOfWarConfig::vision_layers) 的交集
+/// Zones only affect the vision layers in their [`VisionZone::layers`]. Targets are tested on their own layers, so a
+/// ground-only zone shortens the sight of a default provider on ground targets; chunk vision uses the layers a provider
+/// sees that are also in [`FogOfWarConfig::vision_layers`](crate::prelude::FogOfWarConfig::vision_layers)
+///
+/// 环境由默认层和所有 [`FogLayer`](crate::prelude::FogLayer) 共享，区域按各层的迷雾空间解释
+/// The environment is shared by the default layer and every [`FogLayer`](crate::prelude::FogLayer), with zones read in the fog space of each layer
+#[derive(Resource, Clone, Debug, Reflect)]
+pub struct VisionEnvironment {
+    /// 全局视野范围倍率，例如夜晚为 0.5
+    /// Global vision range multiplier, e.g. 0.5 at night
+    pub range_multiplier: f32,
+    /// 区域视野修正，例如缩小视野的雨区
+    /// Spatial vision modifiers, e.g. rain zones that shrink vision
+    pub zones: Vec<VisionZone>,
+}
+
+impl Default for VisionEnvironment {
+    fn default() -> Self {
+        Self {
+            range_multiplier: 1.0,
+            zones: Vec::new(),
+        }
+    }
+}
+
+/// 圆形视野修正区域
+/// Circular vision modifier zone
+#[derive(Clone, Debug, Reflect)]
+pub struct VisionZone {
+    /// 区域中心（世界坐标）
+    /// Zone center (world coordinates)
+    pub center: Vec2,
+    /// 区域半径（世界单位）
+    /// Zone radius (world units)
+    pub radius: f32,
+    /// 区域内视野提供者的范围倍率，不影响区域外的提供者
+    /// Range multiplier for vision providers inside the zone, leaving providers outside untouched
+    pub range_multiplier: f32,
+    /// 区域影响的视野层，[`VisionLayers::ALL`] 影响所有层
+    /// Vision layers the zone affects, [`VisionLayers::ALL`] affecting every layer
+    pub layers: VisionLayers,
+}
+
+impl VisionEnvironment {
+    /// 指定位置的视野范围倍率
+    /// Vision range multiplier at a position
+    pub fn multiplier_at(&self, position: Vec2) -> f32 {
+        self.multiplier_for(position, VisionLayers::NONE)
+    }
+
+    /// 指定位置对视野层的范围倍率，只计入影响所有这些层的区域
+    /// Vision range multiplier at a position for vision layers, counting only zones affecting all of those layers
+    pub fn multiplier_for(&self, position: Vec2, layers: VisionLayers) -> f32 {
+        self.zones
+            .iter()
+            .filter(|zone| {
+                zone.layers.contains(layers)
+                    && zone.center.distance_squared(position) <= zone.radius * zone.radius
+            })
+            .fold(self.range_multiplier, |multiplier, zone| {
+                multiplier * zone.range_multiplier
+            })
+    }
+
+    /// 位于指定位置的视野提供者的有效范围
+    /// Effective range of a vision provider at a position
+    pub fn range_at(&self, range: f32, position: Vec2) -> f32 {
+        (range * self.multiplier_at(position)).max(0.0)
+    }
+
+    /// 位于指定位置的视野提供者在视野层上的有效范围
+    /// Effective range on vision layers of a vision provider at a position
+    pub fn range_for(&self, range: f32, position: Vec2, layers: VisionLayers) -> f32 {
+        (range * self.multiplier_for(position, layers)).max(0.0)
+    }
+}
@@ -1,13 +1,17 @@
+use crate::appearance::FogAppearanceMap;
+use crate::chunk::{ChunkCoord, ChunkLayout};
+use crate::layer::ExtractedFogLayers;
+use crate::sight::FogTeam;
+use crate::vision_layers::VisionLayers;
 use bevy::color::ColorToComponents;
 use bevy::image::Image;
 use bevy::render::render_resource::AsBindGroup;
 use bevy::{
     app::{App, Plugin},
     color::{Color, LinearRgba},
-    math::{Vec2, Vec4},
+    math::{Mat2, Mat4, Rect, Vec2, Vec4},
     prelude::{
-        Camera, Commands, Component, Entity, GlobalTransform, Query, Res, ResMut, Resource, Shader,
-        Time, With,
+        Camera, Commands, Component, Entity, Query, Res, ResMut, Resource, Shader, Time,
     },
     reflect::Reflect,
     render::{
@@ -15,14 +19,15 @@ use bevy::{
         extract_resource::ExtractResource,
         render_resource::{BufferInitDescriptor, BufferUsages, DynamicUniformBuffer, ShaderType},
         renderer::{RenderDevice, RenderQueue},
-        view::ExtractedView,
+        view::{ExtractedView, RenderLayers},
     },
 };
+use bevy::render::{render_asset::RenderAssets, texture::GpuImage};
 use bevy_asset::Handle;
 
-/// 迷雾战争插件配置
-/// Fog of War plugin configuration
-#[derive(Resource)]
+/// 迷雾战争插件配置，作为资源配置默认层，作为组件配置 [`FogLayer`](crate::prelude::FogLayer)
+/// Fog of War plugin configuration, configuring the default layer as a resource and a [`FogLayer`](crate::prelude::FogLayer) as a component
+#[derive(Resource, Component)]
 pub struct FogOfWarConfig {
     /// 区块大小（世界单位）
     /// Chunk size (world units)
@@ -33,6 +38,20 @@ pub struct FogOfWarConfig {
     /// 是否启用调试绘制
     /// Whether to enable debug drawing
     pub debug_draw: bool,
+    /// 区块网格布局
+    /// Chunk grid layout
+    pub layout: ChunkLayout,
+    /// 世界坐标到迷雾空间的线性变换；视野范围和区块在迷雾空间中计算，
+    /// 等距地图可使用 [`FogOfWarConfig::isometric`]
+    /// Linear transform from world to fog space; vision ranges and chunks are computed in fog space,
+    /// isometric maps can use [`FogOfWarConfig::isometric`]
+    pub world_to_fog: Mat2,
+    /// 观看迷雾的队伍，它看不到的其他队伍的 [`FogTarget`](crate::prelude::FogTarget) 会被隐藏
+    /// Team viewing the fog; [`FogTarget`](crate::prelude::FogTarget)s of other teams it cannot see are hidden
+    pub viewer_team: FogTeam,
+    /// 迷雾显示的视野层，只有能看到这些层的视野提供者会驱散迷雾，默认为地面
+    /// Vision layers the fog shows; only vision providers seeing these layers clear the fog, ground by default
+    pub vision_layers: VisionLayers,
 }
 
 impl Default for FogOfWarConfig {
@@ -41,10 +60,55 @@ impl Default for FogOfWarConfig {
             chunk_size: 256.0,
             view_range: 3,
             debug_draw: true,
+            layout: ChunkLayout::default(),
+            world_to_fog: Mat2::IDENTITY,
+            viewer_team: FogTeam::default(),
+            vision_layers: VisionLayers::GROUND,
         }
     }
 }
 
+impl FogOfWarConfig {
+    /// 等距投影的世界到迷雾空间变换
+    /// World to fog space transform of an isometric projection
+    ///
+    /// `tile_size` 是屏幕上菱形图块的宽和高；迷雾空间中的图块为边长 `tile_size.x` 的正方形，
+    /// 因此视野圆在屏幕上显示为椭圆，正方形区块与等距图块网格对齐
+    /// `tile_size` is the width and height of a diamond tile on screen; in fog space a tile is a square with side `tile_size.x`,
+    /// so vision circles appear as ellipses on screen and square chunks line up with the iso tile grid
+    pub fn isometric(tile_size: Vec2) -> Mat2 {
+        let fog_to_world = Mat2::from_cols(
+            Vec2::new(0.5, 0.5 * tile_size.y / tile_size.x),
+            Vec2::new(-0.5, 0.5 * tile_size.y / tile_size.x),
+        );
+        fog_to_world.inverse()
+    }
+
+    /// 将世界坐标转换到迷雾空间
+    /// Convert a world position to fog space
+    pub fn to_fog(&self, position: Vec2) -> Vec2 {
+        self.world_to_fog * position
+    }
+
+    /// 将迷雾空间坐标转换为世界坐标
+    /// Convert a fog space position to world coordinates
+    pub fn to_world(&self, position: Vec2) -> Vec2 {
+        self.world_to_fog.inverse() * position
+    }
+
+    /// 世界坐标所在的区块
+    /// Chunk containing a world position
+    pub fn world_to_chunk(&self, position: Vec2) -> ChunkCoord {
+        self.layout.world_to_chunk(self.to_fog(position), self.chunk_size)
+    }
+
+    /// 区块中心的世界坐标
+    /// World position of a chunk center
+    pub fn chunk_center(&self, coord: ChunkCoord) -> Vec2 {
+        self.to_world(self.layout.chunk_center(coord, self.chunk_size))
+    }
+}
+
 /// 迷雾设置
 /// Fog settings
 #[derive(Component, Clone, Reflect, ExtractComponent)]
@@ -64,6 +128,151 @@ pub struct FogMaterial {
     /// 噪声速度 (用于动态噪声效果)
     /// Noise speed (for dynamic noise effects)
     pub noise_speed: f32,
+    /// 渲染模式（全屏叠加或世界空间图层）
+    /// Render mode (full-screen overlay or world-space layer)
+    pub render_mode: FogRenderMode,
+    /// 受迷雾覆盖的渲染层，其他层上的实体绘制在迷雾之上
+    /// Render layers covered by fog; entities on other layers are drawn above the fog
+    ///
+    /// 只对透明阶段（精灵和透明的 `Mesh2d`）生效；不透明和 alpha 遮罩的 `Mesh2d` 在迷雾之前的主不透明通道中绘制，总是受迷雾覆盖
+    /// Only applies to the transparent phase (sprites and transparent `Mesh2d`); opaque and alpha-mask `Mesh2d` are drawn
+    /// in the main opaque pass before the fog and are always fogged
+    pub fogged_layers: RenderLayers,
+    /// 世界空间外观图，按区域改变迷雾颜色、密度和噪声强度
+    /// World-space appearance map varying fog color, density and noise intensity by region
+    pub appearance: Option<FogAppearanceMap>,
+    /// 程序化噪声，设置后替代噪声纹理
+    /// Procedural noise, replacing the noise texture when set
+    pub procedural_noise: Option<FogNoise>,
+    /// 视野边界的发光轮廓
+    /// Glowing outline at the visibility boundary
+    pub edge: Option<FogEdge>,
+    /// 已探索但当前不可见区域的表现方式
+    /// How explored but currently hidden areas are shown
+    pub explored_style: FogExploredStyle,
+    /// 视野边界的噪声扰动幅度（可见性单位，0 表示不扰动），按 `boundary_speed` 动画
+    /// Noise displacement amplitude of the visibility boundary (in visibility units, 0 disables it), animated by `boundary_speed`
+    pub boundary_distortion: f32,
+    /// 视野边界扰动的噪声频率（每 256 个世界单位的周期数）
+    /// Noise frequency of the boundary distortion (cycles per 256 world units)
+    pub boundary_frequency: f32,
+    /// 视野边界扰动的飘动速度（每秒的噪声周期数，0 表示静止的边界）
+    /// Drift speed of the boundary distortion (noise cycles per second, 0 for still edges)
+    pub boundary_speed: f32,
+    /// 渲染的迷雾层，为 `None` 时渲染默认层
+    /// Fog layer to render, the default layer when `None`
+    pub layer: Option<Entity>,
+}
+
+/// 已探索但当前不可见区域的表现方式
+/// How explored but currently hidden areas are shown
+#[derive(Clone, Debug, Default, PartialEq, Reflect)]
+pub enum FogExploredStyle {
+    /// 与未探索区域一样覆盖迷雾颜色
+    /// Covered with the fog color, like unexplored areas
+    #[default]
+    Fog,
+    /// 读取场景颜色并进行调色，未探索区域仍为不透明迷雾
+    /// Color-grade the scene color, while unexplored areas keep opaque fog
+    ///
+    /// 调色在主通道之后进行，因此也会作用于不受迷雾覆盖的渲染层
+    /// Grading runs after the main pass, so it also applies to render layers that are not fogged
+    Grade {
+        /// 饱和度，0 为完全去色
+        /// Saturation; 0 fully desaturates
+        saturation: f32,
+        /// 亮度倍率，小于 1 时变暗
+        /// Brightness multiplier; below 1 darkens
+        brightness: f32,
+        /// 条带状颜色查找表（宽 N×N，高 N，蓝色分块），在线性场景颜色上应用
+        /// Strip color lookup table (N×N wide, N high, blue in slices), applied to the linear scene color
+        lut: Option<Handle<Image>>,
+    },
+}
+
+/// 视野边界轮廓，在可见性跨过阈值处绘制由可见性纹理梯度计算的轮廓线和光晕
+/// Visibility boundary outline: a rim line and glow drawn where visibility crosses a threshold, computed from the visibility texture gradient
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct FogEdge {
+    /// 轮廓颜色，透明度控制强度
+    /// Outline color; its alpha controls the strength
+    pub color: Color,
+    /// 视野边界对应的可见性阈值 (0.0-1.0)
+    /// Visibility threshold marking the edge of sight (0.0-1.0)
+    pub threshold: f32,
+    /// 轮廓线宽度（屏幕像素）
+    /// Rim line width (screen pixels)
+    pub width: f32,
+    /// 光晕衰减距离（屏幕像素），0 表示只绘制轮廓线
+    /// Glow falloff distance (screen pixels); 0 draws only the rim line
+    pub glow: f32,
+}
+
+impl Default for FogEdge {
+    fn default() -> Self {
+        Self {
+            color: Color::srgba(0.4, 0.9, 1.0, 0.9),
+            threshold: 0.5,
+            width: 1.5,
+            glow: 6.0,
+        }
+    }
+}
+
+/// 程序化迷雾噪声（域扭曲的多倍频 FBM），在世界空间计算并沿流动方向漂移
+/// Procedural fog noise (domain-warped multi-octave FBM), computed in world space and drifting along the flow direction
+///
+/// 材质的 `noise_scale` 缩放噪声频率，`noise_speed` 缩放漂移速度，`noise_intensity` 控制强度
+/// The material `noise_scale` scales the noise frequency, `noise_speed` scales the drift speed and `noise_intensity` controls the strength
+///
+/// 在 `noise_scale` 为 1 时，一个噪声单位对应 256 个世界单位
+/// At a `noise_scale` of 1, one noise unit spans 256 world units
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct FogNoise {
+    /// 倍频数量 (1-8)
+    /// Number of octaves (1-8)
+    pub octaves: u32,
+    /// 每个倍频的频率倍数
+    /// Frequency multiplier per octave
+    pub lacunarity: f32,
+    /// 每个倍频的振幅倍数
+    /// Amplitude multiplier per octave
+    pub gain: f32,
+    /// 流动方向与速度（噪声单位每秒）
+    /// Flow direction and speed (noise units per second)
+    pub flow: Vec2,
+    /// 域扭曲强度，0 表示普通 FBM
+    /// Domain warp strength; 0 gives plain FBM
+    pub warp: f32,
+}
+
+impl Default for FogNoise {
+    fn default() -> Self {
+        Self {
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+            flow: Vec2::new(0.1, 0.03),
+            warp: 1.0,
+        }
+    }
+}
+
+/// 迷雾渲染模式
+/// Fog render mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum FogRenderMode {
+    /// 全屏叠加，覆盖所有受迷雾覆盖的精灵
+    /// Full-screen overlay covering every fogged sprite
+    #[default]
+    Overlay,
+    /// 作为透明阶段项绘制，与精灵按 z 值排序
+    /// Drawn as a transparent phase item, sorted with sprites by z
+    WorldSpace {
+        /// 迷雾图层的 z 值，z 更大的精灵绘制在迷雾之上
+        /// Z of the fog layer; sprites with a greater z are drawn above the fog
+        z: f32,
+    },
 }
 
 impl Default for FogMaterial {
@@ -74,6 +283,16 @@ impl Default for FogMaterial {
             noise_intensity: 1.0,
             noise_scale: 1.0,
             noise_speed: 0.0,
+            render_mode: FogRenderMode::default(),
+            fogged_layers: RenderLayers::default(),
+            appearance: None,
+            procedural_noise: None,
+            edge: None,
+            explored_style: FogExploredStyle::default(),
+            boundary_distortion: 0.0,
+            boundary_frequency: 4.0,
+            boundary_speed: 0.25,
+            layer: None,
         }
     }
 }
@@ -87,7 +306,35 @@ pub struct GpuFogMaterial {
     noise_intensity: f32, // 噪声强度 / Noise intensity
     noise_scale: f32,     // 噪声缩放 / Noise scale
     noise_speed: f32,     // 噪声速度 / Noise speed
-    time: f32             // 当前时间 / Current time (for animated noise)
+    time: f32,            // 当前时间 / Current time (for animated noise)
+    world_from_clip: Mat4, // 裁剪空间到世界空间 / Clip space to world space
+    appearance_bounds: Vec4, // 外观图世界区域 (min.xy, max.xy) / Appearance map world area (min.xy, max.xy)
+    use_appearance: u32,  // 是否使用外观图 / Whether to use the appearance map
+    use_appearance_noise: u32, // 是否使用噪声强度图 / Whether to use the noise intensity map
+    use_procedural_noise: u32, // 是否使用程序化噪声 / Whether to use procedural noise
+    noise_octaves: u32,   // 倍频数量 / Number of octaves
+    noise_lacunarity: f32, // 频率倍数 / Frequency multiplier
+    noise_gain: f32,      // 振幅倍数 / Amplitude multiplier
+    noise_flow: Vec2,     // 流动方向 / Flow direction
+    noise_warp: f32,      // 域扭曲强度 / Domain warp strength
+    edge_color: LinearRgba, // 视野边界轮廓颜色 / Visibility edge color
+    use_edge: u32,        // 是否绘制视野边界 / Whether to draw the visibility edge
+    edge_threshold: f32,  // 视野边界阈值 / Visibility edge threshold
+    edge_width: f32,      // 轮廓线宽度 / Rim line width
+    edge_glow: f32,       // 光晕衰减距离 / Glow falloff distance
+    explored_rect: Vec4,  // 已探索纹理的区块范围 (origin.xy, size.xy) / Chunk area of the explored texture (origin.xy, size.xy)
+    chunk_layout: u32,    // 0 正方形，1 尖顶六边形，2 平顶六边形 / 0 square, 1 pointy hex, 2 flat hex
+    chunk_size: f32,      // 区块大小 / Chunk size
+    world_to_fog: Mat2,   // 世界到迷雾空间的变换 / World to fog space transform
+    use_explored: u32,    // 是否已有已探索纹理 / Whether the explored texture is available
+    explored_style: u32,  // 0 为迷雾，1 为调色 / 0 for fog, 1 for grading
+    grade_saturation: f32, // 调色饱和度 / Grading saturation
+    grade_brightness: f32, // 调色亮度 / Grading brightness
+    use_grade_lut: u32,   // 是否使用颜色查找表 / Whether to use the color lookup table
+    boundary_distortion: f32, // 视野边界扰动幅度 / Boundary distortion amplitude
+    boundary_frequency: f32, // 视野边界扰动频率 / Boundary distortion frequency
+    boundary_speed: f32,  // 视野边界扰动飘动速度 / Boundary distortion drift speed
+    reveal_all: u32,      // 是否揭示整张地图 / Whether the whole map is revealed
 }
 
 #[derive(Default, Resource)]
@@ -95,12 +342,15 @@ pub struct FogOfWarMeta {
     pub gpu_fog_settings: DynamicUniformBuffer<GpuFogMaterial>,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn prepare_fog_settings(
     mut commands: Commands,
     render_device: Res<RenderDevice>,
     render_queue: Res<RenderQueue>,
     mut fog_meta: ResMut<FogOfWarMeta>,
-    views: Query<(Entity, &GlobalTransform, &FogMaterial), With<ExtractedView>>,
+    views: Query<(Entity, &ExtractedView, &FogMaterial)>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    fog_layers: Res<ExtractedFogLayers>,
     time: Res<Time>,
 ) {
     let views_iter = views.iter();
@@ -112,8 +362,40 @@ pub fn prepare_fog_settings(
     else {
         return;
     };
-    for (entity, _transform, fog_settings) in views_iter {
-        
+    for (entity, view, fog_settings) in views_iter {
+        let explored_texture = fog_layers.explored_texture(fog_settings.layer);
+        let use_explored = explored_texture
+            .image
+            .as_ref()
+            .is_some_and(|image| gpu_images.get(image).is_some());
+
+        // 外观图仅在图像上传到 GPU 后启用
+        // The appearance map is only enabled once its images are on the GPU
+        let appearance = fog_settings.appearance.as_ref();
+        let use_appearance =
+            appearance.is_some_and(|map| gpu_images.get(&map.color_density).is_some());
+        let use_appearance_noise = use_appearance
+            && appearance
+                .and_then(|map| map.noise_intensity.as_ref())
+                .is_some_and(|noise| gpu_images.get(noise).is_some());
+        let bounds = appearance.map_or(Rect::default(), |map| map.bounds);
+        let procedural_noise = fog_settings.procedural_noise.unwrap_or_default();
+        let edge = fog_settings.edge.unwrap_or_default();
+        let (explored_style, grade_saturation, grade_brightness, use_grade_lut) =
+            match &fog_settings.explored_style {
+                FogExploredStyle::Fog => (0, 1.0, 1.0, false),
+                FogExploredStyle::Grade {
+                    saturation,
+                    brightness,
+                    lut,
+                } => (
+                    1,
+                    *saturation,
+                    *brightness,
+                    lut.as_ref().is_some_and(|lut| gpu_images.get(lut).is_some()),
+                ),
+            };
+
         let settings = GpuFogMaterial {
             color: fog_settings.color.to_linear(),
             use_noise: if fog_settings.noise_texture.is_some() {
@@ -125,6 +407,40 @@ pub fn prepare_fog_settings(
             noise_scale: fog_settings.noise_scale,
             noise_speed: fog_settings.noise_speed,
             time: time.elapsed_secs(), // 使用当前时间 / Use current time
+            world_from_clip: view.world_from_view.compute_matrix()
+                * view.clip_from_view.inverse(),
+            appearance_bounds: Vec4::new(bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y),
+            use_appearance: use_appearance as u32,
+            use_appearance_noise: use_appearance_noise as u32,
+            use_procedural_noise: fog_settings.procedural_noise.is_some() as u32,
+            noise_octaves: procedural_noise.octaves.clamp(1, 8),
+            noise_lacunarity: procedural_noise.lacunarity,
+            noise_gain: procedural_noise.gain,
+            noise_flow: procedural_noise.flow,
+            noise_warp: procedural_noise.warp,
+            edge_color: edge.color.to_linear(),
+            use_edge: fog_settings.edge.is_some() as u32,
+            edge_threshold: edge.threshold,
+            edge_width: edge.width,
+            edge_glow: edge.glow,
+            explored_rect: Vec4::new(
+                explored_texture.origin.x as f32,
+                explored_texture.origin.y as f32,
+                explored_texture.size.x as f32,
+                explored_texture.size.y as f32,
+            ),
+            chunk_layout: explored_texture.layout as u32,
+            chunk_size: explored_texture.chunk_size,
+            world_to_fog: explored_texture.world_to_fog,
+            use_explored: use_explored as u32,
+            explored_style,
+            grade_saturation,
+            grade_brightness,
+            use_grade_lut: use_grade_lut as u32,
+            boundary_distortion: fog_settings.boundary_distortion,
+            boundary_frequency: fog_settings.boundary_frequency,
+            boundary_speed: fog_settings.boundary_speed,
+            reveal_all: explored_texture.reveal_all as u32,
         };
 
         commands.entity(entity).insert(ViewFogOfWarUniformOffset {
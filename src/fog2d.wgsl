@@ -8,7 +8,35 @@ struct FogMaterial {
     noise_intensity: f32,  // 噪声强度 / noise intensity
     noise_scale: f32,      // 噪声缩放 / noise scale
     noise_speed: f32,      // 噪声速度 / noise speed
-    time: f32             // 当前时间 / current time
+    time: f32,            // 当前时间 / current time
+    world_from_clip: mat4x4<f32>, // 裁剪空间到世界空间 / clip space to world space
+    appearance_bounds: vec4<f32>, // 外观图世界区域 (min.xy, max.xy) / appearance map world area (min.xy, max.xy)
+    use_appearance: u32,  // 是否使用外观图 / whether to use the appearance map
+    use_appearance_noise: u32, // 是否使用噪声强度图 / whether to use the noise intensity map
+    use_procedural_noise: u32, // 是否使用程序化噪声 / whether to use procedural noise
+    noise_octaves: u32,   // 倍频数量 / number of octaves
+    noise_lacunarity: f32, // 频率倍数 / frequency multiplier
+    noise_gain: f32,      // 振幅倍数 / amplitude multiplier
+    noise_flow: vec2<f32>, // 流动方向 / flow direction
+    noise_warp: f32,      // 域扭曲强度 / domain warp strength
+    edge_color: vec4<f32>, // 视野边界轮廓颜色 / visibility edge color
+    use_edge: u32,        // 是否绘制视野边界 / whether to draw the visibility edge
+    edge_threshold: f32,  // 视野边界阈值 / visibility edge threshold
+    edge_width: f32,      // 轮廓线宽度 / rim line width
+    edge_glow: f32,       // 光晕衰减距离 / glow falloff distance
+    explored_rect: vec4<f32>, // 已探索纹理的区块范围 (origin.xy, size.xy) / chunk area of the explored texture (origin.xy, size.xy)
+    chunk_layout: u32,    // 0 正方形，1 尖顶六边形，2 平顶六边形 / 0 square, 1 pointy hex, 2 flat hex
+    chunk_size: f32,      // 区块大小 / chunk size
+    world_to_fog: mat2x2<f32>, // 世界到迷雾空间的变换 / world to fog space transform
+    use_explored: u32,    // 是否已有已探索纹理 / whether the explored texture is available
+    explored_style: u32,  // 0 为迷雾，1 为调色 / 0 for fog, 1 for grading
+    grade_saturation: f32, // 调色饱和度 / grading saturation
+    grade_brightness: f32, // 调色亮度 / grading brightness
+    use_grade_lut: u32,   // 是否使用颜色查找表 / whether to use the color lookup table
+    boundary_distortion: f32, // 视野边界扰动幅度 / boundary distortion amplitude
+    boundary_frequency: f32, // 视野边界扰动频率 / boundary distortion frequency
+    boundary_speed: f32,  // 视野边界扰动飘动速度 / boundary distortion drift speed
+    reveal_all: u32,      // 是否揭示整张地图 / whether the whole map is revealed
 };
 
 @group(0) @binding(0)
@@ -25,15 +53,234 @@ var noise_sampler: sampler;
 @group(0) @binding(3)
 var visibility_texture: texture_2d<f32>;
 
+// 外观图：RGB 为迷雾颜色，A 为密度；噪声强度图使用 R 通道
+// Appearance map: RGB is the fog color, A the density; the noise intensity map uses the R channel
+@group(0) @binding(4)
+var appearance_color_density: texture_2d<f32>;
+
+@group(0) @binding(5)
+var appearance_noise_intensity: texture_2d<f32>;
+
+// 已探索纹理，每个像素对应一个区块：R 为已探索，G 为由命令揭示
+// Explored texture, one pixel per chunk: R is explored, G is revealed by commands
+@group(0) @binding(6)
+var explored_texture: texture_2d<f32>;
+
+// 调色通道：主通道输出的场景颜色和颜色查找表
+// Grading pass: scene color from the main pass and the color lookup table
+@group(1) @binding(0)
+var scene_texture: texture_2d<f32>;
+
+@group(1) @binding(1)
+var scene_sampler: sampler;
+
+@group(1) @binding(2)
+var grade_lut: texture_2d<f32>;
+
+// 将屏幕 UV 转换为世界坐标
+// Convert screen UV to world position
+fn uv_to_world(uv: vec2<f32>) -> vec2<f32> {
+    let clip = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    let world = fog_material.world_from_clip * clip;
+    return world.xy / world.w;
+}
+
+// 一个噪声单位对应的世界单位
+// World units per noise unit
+const NOISE_WORLD_SIZE: f32 = 256.0;
+
+fn hash(p: vec2<f32>) -> f32 {
+    let q = fract(p * vec2<f32>(123.34, 456.21));
+    let r = q + dot(q, q + 45.32);
+    return fract(r.x * r.y);
+}
+
+// 平滑插值的值噪声
+// Value noise with smooth interpolation
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash(i);
+    let b = hash(i + vec2<f32>(1.0, 0.0));
+    let c = hash(i + vec2<f32>(0.0, 1.0));
+    let d = hash(i + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+// 多倍频分形布朗运动，结果归一化到 0-1
+// Multi-octave fractal Brownian motion, normalized to 0-1
+fn fbm(p: vec2<f32>) -> f32 {
+    var position = p;
+    var amplitude = 1.0;
+    var sum = 0.0;
+    var total = 0.0;
+    for (var octave = 0u; octave < fog_material.noise_octaves; octave++) {
+        sum += value_noise(position) * amplitude;
+        total += amplitude;
+        position = position * fog_material.noise_lacunarity + vec2<f32>(17.0, 31.0);
+        amplitude *= fog_material.noise_gain;
+    }
+    return sum / max(total, 1e-4);
+}
+
+// 域扭曲的漂移噪声：扭曲场与噪声本身以不同速度流动，形成翻涌的雾气
+// Domain-warped drifting noise: the warp field and the noise flow at different speeds, giving billowing mist
+fn procedural_noise(world_position: vec2<f32>) -> f32 {
+    let p = world_position / NOISE_WORLD_SIZE * fog_material.noise_scale;
+    let drift = fog_material.noise_flow * fog_material.time * fog_material.noise_speed;
+    let warp = vec2<f32>(
+        fbm(p + drift * 0.5),
+        fbm(p + vec2<f32>(5.2, 1.3) - drift * 0.3),
+    );
+    return fbm(p + (warp - 0.5) * 2.0 * fog_material.noise_warp - drift);
+}
+
+// 视野边界轮廓强度：用可见性纹理的中心差分梯度估算到阈值等值线的屏幕像素距离
+// Visibility edge strength: estimate the screen-pixel distance to the threshold contour from the central-difference gradient of the visibility texture
+fn edge_strength(uv: vec2<f32>, visibility: f32, uv_per_pixel: vec2<f32>) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(visibility_texture));
+    let left = textureSample(visibility_texture, noise_sampler, uv - vec2<f32>(texel.x, 0.0)).a;
+    let right = textureSample(visibility_texture, noise_sampler, uv + vec2<f32>(texel.x, 0.0)).a;
+    let up = textureSample(visibility_texture, noise_sampler, uv - vec2<f32>(0.0, texel.y)).a;
+    let down = textureSample(visibility_texture, noise_sampler, uv + vec2<f32>(0.0, texel.y)).a;
+    // 每屏幕像素的可见性变化
+    // Visibility change per screen pixel
+    let gradient = vec2<f32>(right - left, down - up) / (2.0 * texel) * uv_per_pixel;
+    let gradient_length = length(gradient);
+    if (gradient_length < 1e-5) {
+        return 0.0;
+    }
+    let distance = abs(visibility - fog_material.edge_threshold) / gradient_length;
+
+    let half_width = fog_material.edge_width * 0.5;
+    let line = 1.0 - smoothstep(half_width, half_width + 1.0, distance);
+    var glow = 0.0;
+    if (fog_material.edge_glow > 0.0) {
+        glow = exp(-distance / fog_material.edge_glow) * 0.6;
+    }
+    return max(line, glow);
+}
+
+// 用漂移的噪声扰动可见性阈值，只影响过渡带，使视野边缘呈现雾气消散般的卷须
+// Displace the visibility threshold with drifting noise, only within the transition band, so vision edges look like clearing mist
+fn distort_visibility(visibility: f32, world_position: vec2<f32>) -> f32 {
+    if (fog_material.boundary_distortion <= 0.0) {
+        return visibility;
+    }
+    let p = world_position / NOISE_WORLD_SIZE * fog_material.boundary_frequency;
+    // 卷须沿固定的斜向飘动，与内部噪声的流动无关
+    // Wisps drift along a fixed diagonal, independent of the interior noise flow
+    let drift = vec2<f32>(0.94, 0.34) * fog_material.time * fog_material.boundary_speed;
+    let n = value_noise(p + drift) * 0.6
+        + value_noise(p * 2.3 - drift * 1.7 + vec2<f32>(7.1, 3.7)) * 0.3
+        + value_noise(p * 5.1 + drift * 2.9 + vec2<f32>(1.9, 9.2)) * 0.1;
+    // 完全可见或完全不可见处保持不变
+    // Fully visible and fully hidden areas stay untouched
+    let band = 4.0 * visibility * (1.0 - visibility);
+    return clamp(visibility + (n - 0.5) * 2.0 * fog_material.boundary_distortion * band, 0.0, 1.0);
+}
+
+// 将迷雾空间坐标转换为六边形轴向坐标并取整
+// Convert a fog space position to rounded hex axial coordinates
+fn fog_to_hex(fog_position: vec2<f32>) -> vec2<f32> {
+    let p = fog_position / fog_material.chunk_size;
+    var axial: vec2<f32>;
+    if (fog_material.chunk_layout == 1u) {
+        axial = vec2<f32>(sqrt(3.0) / 3.0 * p.x - p.y / 3.0, 2.0 / 3.0 * p.y);
+    } else {
+        axial = vec2<f32>(2.0 / 3.0 * p.x, -p.x / 3.0 + sqrt(3.0) / 3.0 * p.y);
+    }
+    let cube = vec3<f32>(axial, -axial.x - axial.y);
+    let rounded = round(cube);
+    let diff = abs(rounded - cube);
+    if (diff.x > diff.y && diff.x > diff.z) {
+        return vec2<f32>(-rounded.y - rounded.z, rounded.y);
+    } else if (diff.y > diff.z) {
+        return vec2<f32>(rounded.x, -rounded.x - rounded.z);
+    }
+    return rounded.xy;
+}
+
+// 世界坐标处的已探索和已揭示程度 (0-1)
+// Explored and revealed amounts at a world position (0-1)
+fn fog_state_at(world_position: vec2<f32>) -> vec2<f32> {
+    if (fog_material.use_explored == 0u) {
+        return vec2<f32>(0.0);
+    }
+    let origin = fog_material.explored_rect.xy;
+    let size = fog_material.explored_rect.zw;
+    // 区块在迷雾空间中划分
+    // Chunks are laid out in fog space
+    let fog_position = fog_material.world_to_fog * world_position;
+
+    // 六边形区块按最近的六边形读取，不做插值
+    // Hex chunks are read from the nearest hex without interpolation
+    if (fog_material.chunk_layout != 0u) {
+        let texel = fog_to_hex(fog_position) - origin;
+        if (any(texel < vec2<f32>(0.0)) || any(texel >= size)) {
+            return vec2<f32>(0.0);
+        }
+        let row = size.y - 1.0 - texel.y;
+        return textureLoad(explored_texture, vec2<i32>(i32(texel.x), i32(row)), 0).rg;
+    }
+
+    let chunk = fog_position / fog_material.chunk_size - origin;
+    let explored_uv = vec2<f32>(chunk.x / size.x, 1.0 - chunk.y / size.y);
+    if (any(explored_uv < vec2<f32>(0.0)) || any(explored_uv > vec2<f32>(1.0))) {
+        return vec2<f32>(0.0);
+    }
+    return textureSampleLevel(explored_texture, noise_sampler, explored_uv, 0.0).rg;
+}
+
+// 合并视野可见性与命令揭示的区域
+// Combine the vision visibility with areas revealed by commands
+fn revealed_visibility(visibility: f32, world_position: vec2<f32>) -> f32 {
+    if (fog_material.reveal_all == 1u) {
+        return 1.0;
+    }
+    return max(visibility, fog_state_at(world_position).y);
+}
+
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
     // 初始化迷雾颜色
     // Initialize fog color
     var fog_color = fog_material.color;
+    var density = 1.0;
+    var noise_intensity = fog_material.noise_intensity;
+    let world_position = uv_to_world(in.uv);
+    // 屏幕像素在 UV 中的大小，导数需在统一控制流中计算
+    // Size of a screen pixel in UV; derivatives must be taken in uniform control flow
+    let uv_per_pixel = abs(vec2<f32>(dpdx(in.uv).x, dpdy(in.uv).y));
+
+    // 在世界空间采样外观图，区域外保持材质设置
+    // Sample the appearance map in world space, keeping the material settings outside it
+    if (fog_material.use_appearance == 1u) {
+        let bounds = fog_material.appearance_bounds;
+        let appearance_uv = vec2<f32>(
+            (world_position.x - bounds.x) / (bounds.z - bounds.x),
+            (bounds.w - world_position.y) / (bounds.w - bounds.y),
+        );
+        let appearance = textureSampleLevel(appearance_color_density, noise_sampler, appearance_uv, 0.0);
+        let appearance_noise = textureSampleLevel(appearance_noise_intensity, noise_sampler, appearance_uv, 0.0).r;
+        if (all(appearance_uv >= vec2<f32>(0.0)) && all(appearance_uv <= vec2<f32>(1.0))) {
+            fog_color = vec4<f32>(appearance.rgb, fog_color.a);
+            density = appearance.a;
+            if (fog_material.use_appearance_noise == 1u) {
+                noise_intensity = appearance_noise;
+            }
+        }
+    }
     
-    // 采样可见性纹理获取当前像素的可见性值
-    // Sample visibility texture to get visibility value for current pixel
-    let visibility = textureSample(visibility_texture, noise_sampler, in.uv).r;
+    // 采样可见性纹理：A 为可见性，RGB 为预乘的视野色调
+    // Sample visibility texture: A is the visibility, RGB is the premultiplied vision tint
+    let visibility_sample = textureSample(visibility_texture, noise_sampler, in.uv);
+    let visibility = distort_visibility(
+        revealed_visibility(visibility_sample.a, world_position),
+        world_position,
+    );
+    let vision_tint = visibility_sample.rgb;
     
     // 增强可见性对比度，使可见区域更清晰
     // Enhance visibility contrast to make visible areas clearer
@@ -45,11 +292,22 @@ fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
     
     // 确保透明度在有效范围内
     // Ensure alpha is in valid range
-    alpha = clamp(alpha, 0.0, 1.0);
+    alpha = clamp(alpha * density, 0.0, 1.0);
+
+    // 调色模式下已探索区域不覆盖迷雾，由调色通道处理
+    // In grading mode explored areas are left to the grading pass instead of being fogged
+    if (fog_material.explored_style == 1u) {
+        alpha *= 1.0 - fog_state_at(world_position).x;
+    }
     
-    // 如果启用了噪声纹理，则使用它来修改迷雾效果
-    // If noise texture is enabled, use it to modify the fog effect
-    if (fog_material.use_noise == 1u) {
+    // 程序化噪声优先于噪声纹理
+    // Procedural noise takes precedence over the noise texture
+    if (fog_material.use_procedural_noise == 1u) {
+        let noise_value = procedural_noise(world_position) * noise_intensity;
+        fog_color = vec4<f32>(mix(vec3<f32>(0.0, 0.0, 0.0), fog_color.rgb, noise_value), fog_color.a);
+    } else if (fog_material.use_noise == 1u) {
+        // 如果启用了噪声纹理，则使用它来修改迷雾效果
+        // If noise texture is enabled, use it to modify the fog effect
         // 计算动态UV坐标，基于时间和噪声速度
         // Calculate dynamic UV coordinates based on time and noise speed
         var dynamic_uv = in.uv;
@@ -73,11 +331,59 @@ fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
         
         // 使用噪声值进行插值
         // Use noise value for interpolation with intensity parameter
-        let noise_value = noise.r * fog_material.noise_intensity;
+        let noise_value = noise.r * noise_intensity;
         fog_color = vec4<f32>(mix(vec3<f32>(0.0, 0.0, 0.0), fog_color.rgb, noise_value), fog_color.a);
     }
 
-    // 返回最终颜色，使用基于可见性计算的透明度
-    // Return final color with visibility-based transparency
-    return vec4<f32>(fog_color.rgb, alpha);
+    // 预乘颜色：迷雾覆盖在叠加了视野色调的场景之上
+    // Premultiplied color: fog over the scene with the vision tint added
+    var color = vec4<f32>(fog_color.rgb * alpha + vision_tint * (1.0 - alpha), alpha);
+
+    // 视野边界轮廓叠加在迷雾之上
+    // The visibility edge is composited over the fog
+    if (fog_material.use_edge == 1u) {
+        let edge_alpha = fog_material.edge_color.a * edge_strength(in.uv, visibility, uv_per_pixel);
+        color = vec4<f32>(fog_material.edge_color.rgb * edge_alpha, edge_alpha) + color * (1.0 - edge_alpha);
+    }
+
+    return color;
+}
+
+// 在条带状颜色查找表中查找颜色（宽 N×N，高 N，蓝色分块）
+// Look a color up in a strip lookup table (N×N wide, N high, blue in slices)
+fn apply_lut(color: vec3<f32>) -> vec3<f32> {
+    let size = f32(textureDimensions(grade_lut).y);
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)) * (size - 1.0);
+    let slice = floor(c.b);
+    let slice_mix = c.b - slice;
+    let next_slice = min(slice + 1.0, size - 1.0);
+    let texel = vec2<f32>(c.r + 0.5, c.g + 0.5);
+    let strip_size = vec2<f32>(size * size, size);
+    let low = textureSampleLevel(grade_lut, scene_sampler, (texel + vec2<f32>(slice * size, 0.0)) / strip_size, 0.0).rgb;
+    let high = textureSampleLevel(grade_lut, scene_sampler, (texel + vec2<f32>(next_slice * size, 0.0)) / strip_size, 0.0).rgb;
+    return mix(low, high, slice_mix);
+}
+
+// 调色通道：对已探索但不可见的区域去色、变暗或应用查找表
+// Grading pass: desaturate, darken or look up explored but hidden areas
+@fragment
+fn grade_fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let scene = textureSample(scene_texture, scene_sampler, in.uv);
+    let world_position = uv_to_world(in.uv);
+    let visibility = distort_visibility(
+        revealed_visibility(textureSample(visibility_texture, noise_sampler, in.uv).a, world_position),
+        world_position,
+    );
+    // 与迷雾通道使用相同的可见性曲线
+    // Same visibility curve as the fog pass
+    let hidden = fog_state_at(world_position).x * (1.0 - pow(visibility, 0.5));
+
+    let luminance = dot(scene.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    var graded = mix(vec3<f32>(luminance), scene.rgb, fog_material.grade_saturation)
+        * fog_material.grade_brightness;
+    if (fog_material.use_grade_lut == 1u) {
+        graded = apply_lut(graded);
+    }
+
+    return vec4<f32>(mix(scene.rgb, graded, hidden), scene.a);
 }
@@ -0,0 +1,161 @@
+use crate::chunk::{ChunkCoord, FogChunkManager, update_chunk_visibility};
+use crate::fog::FogOfWarConfig;
+use crate::layer::{FogLayer, InFogLayer};
+use crate::sight::hide_unseen_targets;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+
+/// 离开视野时留下残影的实体，例如建筑
+/// Entity that leaves a ghost behind when it drops out of vision, e.g. a building
+///
+/// 实体从可见变为被迷雾覆盖时，在最后看到的位置生成其精灵的静态副本 [`FogGhost`]；
+/// 残影一直保留到该区域再次可见，届时若原实体仍在原处则重新显示，否则残影消失；残影存在期间原实体被隐藏
+/// When the entity goes from visible to fogged, a static copy of its sprite, a [`FogGhost`], is spawned
+/// at the last seen position; the ghost stays until the area is seen again, which shows the original if
+/// it is still there and otherwise makes the ghost disappear. The original is hidden while its ghost exists
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[require(Sprite)]
+pub struct LeavesGhost;
+
+/// 实体最后被看到时的残影
+/// Ghost of an entity as it was last seen
+#[derive(Component, Clone, Copy, Debug, Reflect)]
+pub struct FogGhost {
+    /// 留下残影的实体，可能已被销毁
+    /// Entity that left the ghost, which may have been despawned
+    pub source: Entity,
+    /// 残影所在的区块
+    /// Chunk the ghost lies in
+    pub chunk: ChunkCoord,
+    /// 残影所在的迷雾层，`None` 为默认层
+    /// Fog layer the ghost lies in, `None` being the default layer
+    pub layer: Option<Entity>,
+}
+
+/// 残影来源查询：实体、变换、精灵、可见性和所属的迷雾层
+/// Ghost source query: entity, transform, sprite, visibility and fog layer
+type GhostSourceQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        &'static Sprite,
+        &'static mut Visibility,
+        Option<&'static InFogLayer>,
+    ),
+    With<LeavesGhost>,
+>;
+
+/// 实体最后可见时的精灵、变换和所属的迷雾层
+/// Sprite, transform and fog layer of an entity when it was last visible
+type GhostSnapshot = (Sprite, Transform, Option<Entity>);
+
+/// 为离开视野的 [`LeavesGhost`] 实体生成残影，并移除区域再次可见的残影
+/// Spawn ghosts for [`LeavesGhost`] entities leaving vision and remove ghosts whose area is seen again
+///
+/// 残影使用实体最后可见那一帧的精灵和变换，残影存在期间隐藏原实体；
+/// 带有 [`InFogLayer`] 的实体按其迷雾层的可见性判断
+/// Ghosts use the sprite and transform of the last frame the entity was visible, and the original is hidden while its
+/// ghost exists; entities with an [`InFogLayer`] follow the visibility of their fog layer
+#[allow(clippy::too_many_arguments)]
+pub fn update_fog_ghosts(
+    mut commands: Commands,
+    config: Res<FogOfWarConfig>,
+    chunk_manager: Res<FogChunkManager>,
+    layers: Query<(&FogOfWarConfig, &FogChunkManager), With<FogLayer>>,
+    mut sources: GhostSourceQuery,
+    ghosts: Query<(Entity, &FogGhost)>,
+    mut last_seen: Local<HashMap<Entity, GhostSnapshot>>,
+    mut hidden: Local<HashSet<Entity>>,
+) {
+    // 迷雾层的配置和区块管理器，迷雾层不存在时为 `None`
+    // Config and chunk manager of a fog layer, `None` when the layer does not exist
+    let fog_layer = |layer: Option<Entity>| match layer {
+        Some(layer) => layers.get(layer).ok(),
+        None => Some((&*config, &*chunk_manager)),
+    };
+
+    // 区域再次可见，或原实体重新进入视野时，残影已过时
+    // A ghost is outdated once its area is visible again or its source is back in vision
+    let mut visible = HashSet::new();
+    for (entity, transform, .., in_layer) in &sources {
+        let Some((config, chunk_manager)) = fog_layer(in_layer.map(|in_layer| in_layer.0)) else {
+            continue;
+        };
+        let chunk = config.world_to_chunk(transform.translation().truncate());
+        if chunk_manager.is_visible(&chunk) {
+            visible.insert(entity);
+        }
+    }
+    let mut ghosted = HashSet::new();
+    for (ghost, fog_ghost) in &ghosts {
+        let area_visible = fog_layer(fog_ghost.layer)
+            .is_none_or(|(_, chunk_manager)| chunk_manager.is_visible(&fog_ghost.chunk));
+        if area_visible || visible.contains(&fog_ghost.source) {
+            commands.entity(ghost).despawn_recursive();
+        } else {
+            ghosted.insert(fog_ghost.source);
+        }
+    }
+
+    // 在离开视野的实体最后被看到的位置留下残影，使用当时的状态而不是迷雾中的当前状态
+    // Leave a ghost where entities leaving vision were last seen, using their state back then rather than
+    // their current state inside the fog
+    for (&entity, (sprite, transform, layer)) in last_seen.iter() {
+        if visible.contains(&entity) || !sources.contains(entity) {
+            continue;
+        }
+        let Some((config, _)) = fog_layer(*layer) else {
+            continue;
+        };
+        commands.spawn((
+            FogGhost {
+                source: entity,
+                chunk: config.world_to_chunk(transform.translation.truncate()),
+                layer: *layer,
+            },
+            sprite.clone(),
+            *transform,
+        ));
+        ghosted.insert(entity);
+    }
+
+    // 残影存在时隐藏原实体，避免同时绘制两次；只恢复由残影隐藏的实体
+    // Hide the original while its ghost exists so it is not drawn twice; only entities hidden for a ghost are restored
+    let mut current = HashMap::new();
+    for (entity, transform, sprite, mut visibility, in_layer) in &mut sources {
+        if ghosted.contains(&entity) {
+            if *visibility != Visibility::Hidden && hidden.insert(entity) {
+                *visibility = Visibility::Hidden;
+            }
+        } else if hidden.remove(&entity) {
+            *visibility = Visibility::Inherited;
+        }
+        if visible.contains(&entity) {
+            let layer = in_layer.map(|in_layer| in_layer.0);
+            current.insert(entity, (sprite.clone(), transform.compute_transform(), layer));
+        }
+    }
+    hidden.retain(|entity| sources.contains(*entity));
+    *last_seen = current;
+}
+
+/// 迷雾残影插件
+/// Fog ghost plugin
+pub struct FogGhostPlugin;
+
+impl Plugin for FogGhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LeavesGhost>()
+            .register_type::<FogGhost>()
+            .add_systems(
+                Update,
+                // 在目标隐藏之后运行，使残影对原实体的隐藏在同一帧生效
+                // Runs after target hiding so hiding the source of a ghost takes effect within the same frame
+                update_fog_ghosts
+                    .after(update_chunk_visibility)
+                    .after(hide_unseen_targets),
+            );
+    }
+}
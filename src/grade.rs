@@ -0,0 +1,234 @@
+use crate::FOG_2D_SHADER_HANDLE;
+use crate::fog::{FogExploredStyle, FogMaterial, ViewFogOfWarUniformOffset};
+use crate::node::{FogOfWar2dPipeline, ViewFogBindGroup};
+use bevy::core_pipeline::core_2d::graph::{Core2d, Node2d};
+use bevy::core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state;
+use bevy::ecs::query::QueryItem;
+use bevy::ecs::system::lifetimeless::Read;
+use bevy::image::BevyDefault;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{
+    NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+};
+use bevy::render::render_resource::binding_types::{sampler, texture_2d};
+use bevy::render::render_resource::*;
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::{FallbackImage, GpuImage};
+use bevy::render::view::{ExtractedView, ViewTarget};
+use bevy::render::{Render, RenderApp, RenderSet};
+
+/// 调色通道管线
+/// Grading pass pipeline
+#[derive(Resource)]
+pub struct FogGradePipeline {
+    /// 迷雾绑定组布局 (group 0)
+    /// Fog bind group layout (group 0)
+    fog_layout: BindGroupLayout,
+    /// 场景颜色与查找表布局 (group 1)
+    /// Scene color and lookup table layout (group 1)
+    grade_layout: BindGroupLayout,
+    sampler: Sampler,
+}
+
+impl FromWorld for FogGradePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let fog_layout = world
+            .resource::<FogOfWar2dPipeline>()
+            .bind_group_layout
+            .clone();
+        let render_device = world.resource::<RenderDevice>();
+
+        let grade_layout = render_device.create_bind_group_layout(
+            "fog_grade_layout",
+            &BindGroupLayoutEntries::sequential(
+                ShaderStages::FRAGMENT,
+                (
+                    // 场景颜色 (Scene color)
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    sampler(SamplerBindingType::Filtering),
+                    // 颜色查找表 (Color lookup table)
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                ),
+            ),
+        );
+
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("fog_grade_sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..default()
+        });
+
+        Self {
+            fog_layout,
+            grade_layout,
+            sampler,
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for FogGradePipeline {
+    /// 视图是否使用 HDR 主纹理
+    /// Whether the view renders to an HDR main texture
+    type Key = bool;
+
+    fn specialize(&self, hdr: Self::Key) -> RenderPipelineDescriptor {
+        let format = if hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("fog_grade_pipeline".into()),
+            layout: vec![self.fog_layout.clone(), self.grade_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: FOG_2D_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "grade_fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            push_constant_ranges: vec![],
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// 视图的调色管线，仅在调色模式下存在
+/// Grading pipeline of a view, only present in grading mode
+#[derive(Component)]
+pub struct ViewFogGradePipeline(CachedRenderPipelineId);
+
+/// 为调色模式的视图特化调色管线
+/// Specialize the grading pipeline for views in grading mode
+pub fn prepare_fog_grade_pipelines(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    grade_pipeline: Res<FogGradePipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<FogGradePipeline>>,
+    views: Query<(Entity, &ExtractedView, &FogMaterial)>,
+) {
+    for (entity, view, fog_material) in &views {
+        if let FogExploredStyle::Grade { .. } = fog_material.explored_style {
+            let pipeline = pipelines.specialize(&pipeline_cache, &grade_pipeline, view.hdr);
+            commands
+                .entity(entity)
+                .insert(ViewFogGradePipeline(pipeline));
+        } else {
+            commands.entity(entity).remove::<ViewFogGradePipeline>();
+        }
+    }
+}
+
+#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
+pub struct FogGradeLabel;
+
+/// 调色节点：读取主通道的场景颜色，对已探索但不可见的区域调色
+/// Grading node: reads the scene color of the main pass and grades explored but hidden areas
+#[derive(Default)]
+pub struct FogGradeNode;
+
+impl ViewNode for FogGradeNode {
+    type ViewQuery = (
+        Read<ViewTarget>,
+        Read<FogMaterial>,
+        Read<ViewFogGradePipeline>,
+        Read<ViewFogBindGroup>,
+        Read<ViewFogOfWarUniformOffset>,
+    );
+
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, fog_material, grade_pipeline_id, fog_bind_group, fog_offset): QueryItem<
+            Self::ViewQuery,
+        >,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let grade_pipeline = world.resource::<FogGradePipeline>();
+        let Some(pipeline) = world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(grade_pipeline_id.0)
+        else {
+            return Ok(());
+        };
+
+        // 查找表未加载时使用回退图像，着色器中不会采样
+        // Use the fallback image while the lookup table is not loaded; the shader does not sample it then
+        let lut = match &fog_material.explored_style {
+            FogExploredStyle::Grade { lut, .. } => lut.as_ref(),
+            FogExploredStyle::Fog => None,
+        };
+        let fallback_image = world.resource::<FallbackImage>();
+        let lut_view = lut
+            .and_then(|lut| world.resource::<RenderAssets<GpuImage>>().get(lut))
+            .map_or(&fallback_image.d2.texture_view, |gpu_image| {
+                &gpu_image.texture_view
+            });
+
+        let post_process = view_target.post_process_write();
+        let grade_bind_group = render_context.render_device().create_bind_group(
+            "fog_grade_bind_group",
+            &grade_pipeline.grade_layout,
+            &BindGroupEntries::sequential((post_process.source, &grade_pipeline.sampler, lut_view)),
+        );
+
+        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("fog_grade_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: post_process.destination,
+                resolve_target: None,
+                ops: Operations::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_render_pipeline(pipeline);
+        render_pass.set_bind_group(0, &fog_bind_group.0, &[fog_offset.offset]);
+        render_pass.set_bind_group(1, &grade_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+
+        Ok(())
+    }
+}
+
+/// 已探索区域调色插件
+/// Explored area grading plugin
+pub struct FogGradePlugin;
+
+impl Plugin for FogGradePlugin {
+    fn build(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<SpecializedRenderPipelines<FogGradePipeline>>()
+            .add_systems(
+                Render,
+                prepare_fog_grade_pipelines.in_set(RenderSet::Prepare),
+            )
+            .add_render_graph_node::<ViewNodeRunner<FogGradeNode>>(Core2d, FogGradeLabel)
+            .add_render_graph_edges(
+                Core2d,
+                (Node2d::EndMainPass, FogGradeLabel, Node2d::Tonemapping),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<FogGradePipeline>();
+    }
+}
@@ -0,0 +1,184 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+use crate::vision_layers::VisionLayers;
+
+/// 地形高度图，用于高地视野规则
+/// Terrain height map, used for the high-ground vision rule
+///
+/// 视野提供者的视点高度为其所在位置的地形高度加上 [`VisionProvider::elevation`](crate::prelude::VisionProvider::elevation)；
+/// 提供者能看到地形不高于视点的低处，但看不到更高的台地。高度图是迷雾空间中的正方形网格，
+/// 使用方形区块布局时，将单元格边长设为区块大小即可按区块设置高度
+/// The eye height of a vision provider is the terrain height at its position plus [`VisionProvider::elevation`](crate::prelude::VisionProvider::elevation);
+/// providers see down into terrain no higher than their eye but not up onto higher ledges. The height map is a square grid
+/// in fog space; with the square chunk layout, a cell size equal to the chunk size sets heights per chunk
+///
+/// 地形只遮挡 [`VisionHeightMap::layers`] 中的视野层，例如默认情况下空中目标不会被台地遮挡，
+/// [`VisionProvider::lives_on`](crate::prelude::VisionProvider::lives_on) 为空中的提供者也能看到更高的台地
+/// The terrain only occludes vision layers in [`VisionHeightMap::layers`], e.g. by default air targets are not hidden by ledges,
+/// and providers whose [`VisionProvider::lives_on`](crate::prelude::VisionProvider::lives_on) is air see onto higher ledges
+///
+/// 高度图由默认层和所有 [`FogLayer`](crate::prelude::FogLayer) 共享，按各层的迷雾空间采样
+/// The height map is shared by the default layer and every [`FogLayer`](crate::prelude::FogLayer), sampled in the fog space of each layer
+#[derive(Resource, Clone, Default)]
+pub struct VisionHeightMap {
+    /// 网格左下角在迷雾空间中的位置
+    /// Bottom-left corner of the grid in fog space
+    pub origin: Vec2,
+    /// 单元格边长（迷雾空间单位）
+    /// Cell side length (fog space units)
+    pub cell_size: f32,
+    /// 地形遮挡的视野层，默认为地面
+    /// Vision layers occluded by the terrain, ground by default
+    pub layers: VisionLayers,
+    /// 网格的单元格数量 (Number of cells in the grid)
+    size: UVec2,
+    /// 按行存储的高度，第一行位于 y 最小处 (Heights stored by row, with the first row at the smallest y)
+    heights: Vec<f32>,
+    /// 供计算着色器采样的高度纹理 (Height texture sampled by the compute shader)
+    image: Option<Handle<Image>>,
+}
+
+impl VisionHeightMap {
+    /// 创建所有高度为 0 的高度图
+    /// Create a height map with every height at 0
+    pub fn new(origin: Vec2, cell_size: f32, size: UVec2) -> Self {
+        Self {
+            origin,
+            cell_size,
+            layers: VisionLayers::GROUND,
+            size,
+            heights: vec![0.0; (size.x * size.y) as usize],
+            image: None,
+        }
+    }
+
+    /// 从图像的 R 通道创建高度图，每个像素对应一个单元格，图像第一行位于 y 最大处
+    /// Create a height map from the R channel of an image, one pixel per cell, with the first image row at the greatest y
+    ///
+    /// 通道值 0.0-1.0 映射到高度 0 到 `max_height`，图像格式不支持读取像素时返回 `None`
+    /// Channel values 0.0-1.0 map to heights 0 to `max_height`; returns `None` when pixels of the image format cannot be read
+    pub fn from_image(
+        image: &Image,
+        origin: Vec2,
+        cell_size: f32,
+        max_height: f32,
+    ) -> Option<Self> {
+        let size = image.size();
+        let mut height_map = Self::new(origin, cell_size, size);
+        for row in 0..size.y {
+            for column in 0..size.x {
+                let value = match image.get_color_at(column, row).ok()? {
+                    Color::Srgba(color) => color.red,
+                    color => color.to_linear().red,
+                };
+                height_map.set(UVec2::new(column, size.y - 1 - row), value * max_height);
+            }
+        }
+        Some(height_map)
+    }
+
+    /// 网格的单元格数量
+    /// Number of cells in the grid
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    /// 单元格的高度，网格外为 0
+    /// Height of a cell, 0 outside the grid
+    pub fn height(&self, cell: UVec2) -> f32 {
+        if cell.x < self.size.x && cell.y < self.size.y {
+            self.heights[(cell.y * self.size.x + cell.x) as usize]
+        } else {
+            0.0
+        }
+    }
+
+    /// 设置单元格的高度，网格外的单元格被忽略
+    /// Set the height of a cell; cells outside the grid are ignored
+    pub fn set(&mut self, cell: UVec2, height: f32) {
+        if cell.x < self.size.x && cell.y < self.size.y {
+            self.heights[(cell.y * self.size.x + cell.x) as usize] = height;
+        }
+    }
+
+    /// 地形是否遮挡视野层上的视野
+    /// Whether the terrain occludes sight on vision layers
+    pub fn occludes(&self, layers: VisionLayers) -> bool {
+        self.layers.contains(layers)
+    }
+
+    /// 迷雾空间位置的地形高度，网格外为 0
+    /// Terrain height at a fog space position, 0 outside the grid
+    pub fn height_at(&self, fog_position: Vec2) -> f32 {
+        if self.heights.is_empty() || self.cell_size <= 0.0 {
+            return 0.0;
+        }
+        let cell = ((fog_position - self.origin) / self.cell_size).floor();
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return 0.0;
+        }
+        self.height(cell.as_uvec2())
+    }
+
+    /// 供计算着色器采样的高度纹理（R32Float），第一行位于 y 最大处；高度图为空时为 `None`
+    /// Height texture (R32Float) sampled by the compute shader, with the first row at the greatest y; `None` for an empty height map
+    pub fn image(&self) -> Option<&Handle<Image>> {
+        self.image.as_ref()
+    }
+}
+
+/// 高度图变化时重建高度纹理
+/// Rebuild the height texture when the height map changes
+pub fn update_height_texture(
+    mut height_map: ResMut<VisionHeightMap>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !height_map.is_changed() {
+        return;
+    }
+    // 纹理句柄不算作高度图的修改
+    // The texture handle does not count as a change of the height map
+    let height_map = height_map.bypass_change_detection();
+    if height_map.heights.is_empty() {
+        height_map.image = None;
+        return;
+    }
+
+    let size = height_map.size;
+    let mut data = Vec::with_capacity(height_map.heights.len() * 4);
+    for row in 0..size.y {
+        for column in 0..size.x {
+            let height = height_map.height(UVec2::new(column, size.y - 1 - row));
+            data.extend_from_slice(&height.to_le_bytes());
+        }
+    }
+    let image = Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R32Float,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+
+    match &height_map.image {
+        Some(handle) => images.insert(handle, image),
+        None => height_map.image = Some(images.add(image)),
+    }
+}
+
+/// 地形高度插件
+/// Terrain height plugin
+pub struct VisionHeightPlugin;
+
+impl Plugin for VisionHeightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VisionHeightMap>()
+            .add_systems(Update, update_height_texture);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::sight::{FogTarget, FogTeam, TeamSight, update_team_sight};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// 目标最后被看到时的状态
+/// State of a target when it was last seen
+#[derive(Clone, Copy, Debug, PartialEq, Reflect)]
+pub struct LastKnownPosition {
+    /// 世界坐标 (World position)
+    pub position: Vec2,
+    /// 世界空间速度（每秒） (World-space velocity per second)
+    pub velocity: Vec2,
+    /// 最后被看到的时间（秒） (Time last seen, in seconds)
+    pub time: f32,
+}
+
+/// 目标离开队伍视野的事件
+/// Event sent when a target drops out of the sight of a team
+#[derive(Event, Clone, Copy, Debug)]
+pub struct TargetLastSeen {
+    pub team: FogTeam,
+    pub target: Entity,
+    pub last_known: LastKnownPosition,
+}
+
+/// 每支队伍对视野外敌方目标的情报记忆
+/// Intel memory of each team about enemy targets out of its sight
+///
+/// 目标离开视野时记录其最后的位置、时间和速度，再次被看到时移除；
+/// 目标在视野外被销毁时记录会保留，直到调用 [`forget`](Self::forget)
+/// The last position, time and velocity are recorded when a target leaves sight and removed once it is
+/// seen again; records of targets despawned out of sight stay until [`forget`](Self::forget) is called
+#[derive(Resource, Default)]
+pub struct LastKnownPositions {
+    teams: HashMap<FogTeam, HashMap<Entity, LastKnownPosition>>,
+}
+
+impl LastKnownPositions {
+    /// 队伍记录的目标最后位置
+    /// Last known position of a target recorded by a team
+    pub fn get(&self, team: FogTeam, target: Entity) -> Option<&LastKnownPosition> {
+        self.teams.get(&team)?.get(&target)
+    }
+
+    /// 队伍记录的所有目标最后位置
+    /// Every last known position recorded by a team
+    pub fn iter(&self, team: FogTeam) -> impl Iterator<Item = (Entity, &LastKnownPosition)> {
+        self.teams
+            .get(&team)
+            .into_iter()
+            .flatten()
+            .map(|(target, last_known)| (*target, last_known))
+    }
+
+    /// 忘记队伍记录的目标
+    /// Forget a target recorded by a team
+    pub fn forget(&mut self, team: FogTeam, target: Entity) -> Option<LastKnownPosition> {
+        self.teams.get_mut(&team)?.remove(&target)
+    }
+
+    /// 忘记队伍记录的所有目标
+    /// Forget every target recorded by a team
+    pub fn clear(&mut self, team: FogTeam) {
+        self.teams.remove(&team);
+    }
+}
+
+/// 记录离开视野的目标的最后位置，移除再次被看到的目标
+/// Record the last position of targets leaving sight and remove targets seen again
+pub fn update_last_known_positions(
+    time: Res<Time>,
+    sight: Res<TeamSight>,
+    targets: Query<(Entity, &GlobalTransform), With<FogTarget>>,
+    mut last_known_positions: ResMut<LastKnownPositions>,
+    mut events: EventWriter<TargetLastSeen>,
+    mut tracked: Local<HashMap<Entity, LastKnownPosition>>,
+) {
+    // 离开视野的目标在上一帧仍可见，使用上一帧的状态
+    // Targets leaving sight were still visible last frame, so use last frame's state
+    for (team, target) in sight.lost() {
+        let Some(&last_known) = tracked.get(&target) else {
+            continue;
+        };
+        last_known_positions
+            .teams
+            .entry(team)
+            .or_default()
+            .insert(target, last_known);
+        events.send(TargetLastSeen {
+            team,
+            target,
+            last_known,
+        });
+    }
+    for (&team, records) in &mut last_known_positions.teams {
+        records.retain(|target, _| !sight.is_seen(team, *target));
+    }
+
+    let now = time.elapsed_secs();
+    let delta = time.delta_secs();
+    let mut current = HashMap::new();
+    for (target, transform) in &targets {
+        let position = transform.translation().truncate();
+        let velocity = match tracked.get(&target) {
+            Some(previous) if delta > 0.0 => (position - previous.position) / delta,
+            _ => Vec2::ZERO,
+        };
+        current.insert(
+            target,
+            LastKnownPosition {
+                position,
+                velocity,
+                time: now,
+            },
+        );
+    }
+    *tracked = current;
+}
+
+/// 最后已知位置插件
+/// Last known position plugin
+pub struct LastKnownPositionPlugin;
+
+impl Plugin for LastKnownPositionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LastKnownPosition>()
+            .init_resource::<LastKnownPositions>()
+            .add_event::<TargetLastSeen>()
+            .add_systems(Update, update_last_known_positions.after(update_team_sight));
+    }
+}
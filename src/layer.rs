@@ -0,0 +1,76 @@
+use crate::chunk::{FogChunkManager, FogExploredTexture};
+use crate::fog::FogOfWarConfig;
+use bevy::prelude::*;
+use bevy::render::{Extract, ExtractSchedule, RenderApp};
+use bevy::utils::HashMap;
+
+/// 独立的迷雾层，例如只在小地图上显示的情报迷雾或建筑的不同楼层
+/// Independent fog layer, e.g. a minimap-only intel fog or separate floors of a building
+///
+/// 每个迷雾层实体拥有自己的 [`FogOfWarConfig`]、[`FogChunkManager`] 和 [`FogExploredTexture`]；
+/// 全局资源构成默认层。视野提供者通过 [`InFogLayer`] 加入迷雾层，相机通过 [`FogMaterial::layer`](crate::prelude::FogMaterial::layer) 选择要渲染的层
+/// Each fog layer entity owns its own [`FogOfWarConfig`], [`FogChunkManager`] and [`FogExploredTexture`];
+/// the global resources form the default layer. Vision providers join a layer with [`InFogLayer`], and cameras pick the layer to render with [`FogMaterial::layer`](crate::prelude::FogMaterial::layer)
+///
+/// 迷雾层的区块实体带有指向该层的 [`InFogLayer`]，[`FogCommands::layer`](crate::prelude::FogCommands::layer) 返回作用于该层的命令
+/// Chunk entities of a fog layer carry an [`InFogLayer`] pointing at it, and [`FogCommands::layer`](crate::prelude::FogCommands::layer) returns commands acting on it
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[require(FogOfWarConfig, FogChunkManager, FogExploredTexture)]
+pub struct FogLayer;
+
+/// 视野提供者或区块所属的迷雾层，没有该组件的提供者和区块属于默认层
+/// Fog layer a vision provider or chunk belongs to; providers and chunks without it belong to the default layer
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Reflect)]
+pub struct InFogLayer(pub Entity);
+
+/// 渲染世界中各迷雾层的已探索纹理，`None` 为默认层
+/// Explored textures of each fog layer in the render world, `None` being the default layer
+#[derive(Resource, Default)]
+pub struct ExtractedFogLayers {
+    pub explored_textures: HashMap<Option<Entity>, FogExploredTexture>,
+    empty: FogExploredTexture,
+}
+
+impl ExtractedFogLayers {
+    /// 迷雾层的已探索纹理，迷雾层不存在时为空纹理
+    /// Explored texture of a fog layer, empty when the layer does not exist
+    pub fn explored_texture(&self, layer: Option<Entity>) -> &FogExploredTexture {
+        self.explored_textures.get(&layer).unwrap_or(&self.empty)
+    }
+}
+
+/// 提取默认层和所有迷雾层的已探索纹理
+/// Extract the explored textures of the default layer and every fog layer
+pub fn extract_fog_layers(
+    mut extracted: ResMut<ExtractedFogLayers>,
+    explored_texture: Extract<Res<FogExploredTexture>>,
+    layers: Extract<Query<(Entity, &FogExploredTexture), With<FogLayer>>>,
+) {
+    extracted.explored_textures.clear();
+    extracted
+        .explored_textures
+        .insert(None, explored_texture.clone());
+    extracted.explored_textures.extend(
+        layers
+            .iter()
+            .map(|(layer, explored_texture)| (Some(layer), explored_texture.clone())),
+    );
+}
+
+/// 迷雾层插件
+/// Fog layer plugin
+pub struct FogLayerPlugin;
+
+impl Plugin for FogLayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<FogLayer>()
+            .register_type::<InFogLayer>();
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<ExtractedFogLayers>()
+            .add_systems(ExtractSchedule, extract_fog_layers);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::bitset::ChunkBitSet;
+use crate::chunk::{FogChunkManager, FogReveal, update_chunk_visibility};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use core::hash::Hash;
+use core::marker::PhantomData;
+
+/// 单个关卡保存的迷雾状态
+/// Fog state stashed for a single level
+#[derive(Default)]
+pub(crate) struct FogLevelState {
+    pub(crate) explored_chunks: ChunkBitSet,
+    pub(crate) reveals: Vec<FogReveal>,
+    pub(crate) reveal_all: bool,
+}
+
+/// 按关卡保存的迷雾状态
+/// Fog state kept per level
+///
+/// 切换关卡时保存当前关卡的已探索区块和揭示，并恢复目标关卡的状态；
+/// 第一次切换时，已有的迷雾状态归属于目标关卡。视野提供者的视野在新关卡中重新计算，不会把离开关卡的视野带入新关卡
+/// Switching levels stashes the explored chunks and reveals of the current level and restores those of the target level;
+/// on the first switch the existing fog state is adopted by the target level. Vision providers are recomputed in the new
+/// level, so the sight of the outgoing level never carries over
+///
+/// 只保存和恢复默认层，[`FogLayer`](crate::prelude::FogLayer) 实体的迷雾状态不随关卡切换
+/// Only the default layer is stashed and restored; the fog state of [`FogLayer`](crate::prelude::FogLayer) entities does not follow level switches
+#[derive(Resource)]
+pub struct FogLevels<K: Eq + Hash + Send + Sync + 'static> {
+    current: Option<K>,
+    stashed: HashMap<K, FogLevelState>,
+}
+
+impl<K: Eq + Hash + Send + Sync + 'static> Default for FogLevels<K> {
+    fn default() -> Self {
+        Self {
+            current: None,
+            stashed: HashMap::default(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Send + Sync + 'static> FogLevels<K> {
+    /// 当前关卡
+    /// Current level
+    pub fn current(&self) -> Option<&K> {
+        self.current.as_ref()
+    }
+
+    /// 关卡是否有保存的迷雾状态
+    /// Whether a level has a stashed fog state
+    pub fn is_stashed(&self, level: &K) -> bool {
+        self.stashed.contains_key(level)
+    }
+
+    /// 切换到关卡，保存当前关卡的迷雾状态并恢复目标关卡的状态
+    /// Switch to a level, stashing the fog state of the current level and restoring that of the target
+    pub fn switch_to(&mut self, chunk_manager: &mut FogChunkManager, level: K) {
+        match self.current.take() {
+            Some(current) if current == level => self.current = Some(current),
+            Some(current) => {
+                let restored = self.stashed.remove(&level).unwrap_or_default();
+                self.stashed
+                    .insert(current, chunk_manager.swap_level(restored));
+                self.current = Some(level);
+            }
+            None => self.current = Some(level),
+        }
+    }
+
+    /// 清除关卡的迷雾状态；清除当前关卡时重置迷雾
+    /// Clear the fog state of a level; clearing the current level resets the fog
+    pub fn clear(&mut self, chunk_manager: &mut FogChunkManager, level: &K) {
+        if self.current.as_ref() == Some(level) {
+            chunk_manager.reset();
+        } else {
+            self.stashed.remove(level);
+        }
+    }
+
+    /// 清除所有关卡保存的迷雾状态，当前关卡不受影响
+    /// Clear the stashed fog state of every level, leaving the current level untouched
+    pub fn clear_stashed(&mut self) {
+        self.stashed.clear();
+    }
+}
+
+/// 状态切换时切换迷雾关卡
+/// Switch fog levels on state transitions
+pub fn switch_fog_level<S: States>(
+    mut transitions: EventReader<StateTransitionEvent<S>>,
+    mut levels: ResMut<FogLevels<S>>,
+    mut chunk_manager: ResMut<FogChunkManager>,
+) {
+    for transition in transitions.read() {
+        if let Some(entered) = &transition.entered {
+            levels.switch_to(&mut chunk_manager, entered.clone());
+        }
+    }
+}
+
+/// 按 Bevy 状态保存迷雾状态的插件，每个状态值对应一个关卡
+/// Plugin keeping fog state per Bevy state, one level per state value
+///
+/// 需要在 [`ZingFogPlugins`](crate::prelude::ZingFogPlugins) 之后添加，状态 `S` 需已初始化
+/// Add it after [`ZingFogPlugins`](crate::prelude::ZingFogPlugins), with the state `S` already initialized
+pub struct FogStatePlugin<S: States>(PhantomData<S>);
+
+impl<S: States> Default for FogStatePlugin<S> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<S: States> Plugin for FogStatePlugin<S> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FogLevels<S>>().add_systems(
+            Update,
+            switch_fog_level::<S>.before(update_chunk_visibility),
+        );
+    }
+}
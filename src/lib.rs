@@ -1,21 +1,43 @@
+extern crate alloc;
+
 use crate::chunk::VisionProvider;
+use crate::environment::VisionEnvironment;
+use crate::ghost::FogGhostPlugin;
+use crate::grade::FogGradePlugin;
+use crate::height::VisionHeightPlugin;
+use crate::last_known::LastKnownPositionPlugin;
+use crate::layer::FogLayerPlugin;
+use crate::mask::FogMaskPlugin;
+use crate::reveal::TemporaryRevealPlugin;
+use crate::sight::FogSightPlugin;
+use crate::stamp::VisionStampPlugin;
 use crate::vision_compute::VisionComputePlugin;
+use crate::vision_layers::VisionLayers;
 use crate::{
     chunk::FogChunkPlugin,
     fog::{FogMaterial, FogOfWarConfig, FogOfWarMeta, prepare_fog_settings},
-    node::{FogNode2d, FogNode2dLabel, FogOfWar2dPipeline},
+    node::{
+        DrawFog2d, ExtractedFogRenderLayers, FogOfWar2dPipeline, RemovedFogRenderLayers,
+        collect_removed_render_layers, extract_fog_render_layers,
+        prepare_fog_bind_groups, queue_fog, sort_unfogged_items,
+    },
     vision_compute::{VisionComputeNode, VisionComputePipeline},
 };
 use bevy::prelude::IntoSystemConfigs;
 use bevy::render::render_resource::TextureFormat;
 use bevy::render::sync_component::SyncComponentPlugin;
 use bevy::{
-    app::{App, Plugin},
-    core_pipeline::core_2d::graph::{Core2d, Node2d},
+    app::{App, Last, Plugin},
+    core_pipeline::core_2d::{
+        Transparent2d,
+        graph::{Core2d, Node2d},
+    },
     prelude::Shader,
     render::{
-        Render, RenderApp, RenderSet,
+        ExtractSchedule, Render, RenderApp, RenderSet,
         extract_component::ExtractComponentPlugin,
+        render_phase::{AddRenderCommand, sort_phase_system},
+        render_resource::SpecializedRenderPipelines,
         extract_resource::ExtractResourcePlugin,
         render_graph::{RenderGraphApp, ViewNodeRunner},
     },
@@ -33,12 +55,44 @@ mod chunk;
 
 mod vision_compute;
 
+mod mask;
+
+mod stamp;
+
+mod environment;
+
+mod appearance;
+
+mod grade;
+
+mod bitset;
+
+mod commands;
+
+mod reveal;
+
+mod level;
+
+mod layer;
+
+mod ghost;
+
+mod sight;
+
+mod last_known;
+
+mod vision_layers;
+
+mod height;
+
 #[cfg(feature = "2d")]
 pub const FOG_2D_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(2645352199453808407);
 pub const VISION_COMPUTE_SHADER_HANDLE: Handle<Shader> =
     Handle::weak_from_u128(2645352199453808408);
 
-pub const VISIBILITY_TEXTURE_FORMAT: TextureFormat = TextureFormat::R32Float;
+/// 可见性纹理格式：RGB 为预乘的视野色调，A 为可见性
+/// Visibility texture format: RGB holds the premultiplied vision tint, A holds the visibility
+pub const VISIBILITY_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
 pub const VISIBILITY_TEXTURE_SIZE: u32 = 1024;
 
 pub struct ZingFogPlugins;
@@ -48,35 +102,61 @@ impl Plugin for ZingFogPlugins {
         #[cfg(feature = "2d")]
         load_internal_asset!(app, FOG_2D_SHADER_HANDLE, "fog2d.wgsl", Shader::from_wgsl);
 
-        app.init_resource::<FogOfWarConfig>();
+        app.init_resource::<FogOfWarConfig>()
+            .init_resource::<VisionEnvironment>()
+            .register_type::<VisionEnvironment>()
+            .register_type::<VisionLayers>();
+
+        // 渲染层的移除在主世界收集，提取阶段只同步变化
+        // Render layer removals are collected in the main world so extraction only syncs changes
+        app.init_resource::<RemovedFogRenderLayers>()
+            .add_systems(Last, collect_removed_render_layers);
 
         app.register_type::<FogMaterial>()
             .add_plugins(ExtractComponentPlugin::<FogMaterial>::default())
             .add_plugins(ExtractComponentPlugin::<VisionProvider>::default())
             .add_plugins(FogChunkPlugin)
+            .add_plugins(FogLayerPlugin)
+            .add_plugins(VisionHeightPlugin)
+            .add_plugins(FogMaskPlugin)
+            .add_plugins(VisionStampPlugin)
+            .add_plugins(TemporaryRevealPlugin)
+            .add_plugins(FogGhostPlugin)
+            .add_plugins(FogSightPlugin)
+            .add_plugins(LastKnownPositionPlugin)
+            .add_plugins(FogGradePlugin)
             .add_plugins(VisionComputePlugin);
 
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
         };
 
-        // 将迷雾节点放在 MainTransparentPass 和 EndMainPass 之间
-        // Place fog node between MainTransparentPass and EndMainPass
+        // 迷雾作为透明阶段项绘制，视野计算需在主通道之前完成
+        // Fog is drawn as a transparent phase item, so vision compute runs before the main passes
         render_app
             .init_resource::<FogOfWarMeta>()
+            .init_resource::<ExtractedFogRenderLayers>()
+            .init_resource::<SpecializedRenderPipelines<FogOfWar2dPipeline>>()
+            .add_render_command::<Transparent2d, DrawFog2d>()
+            .add_systems(ExtractSchedule, extract_fog_render_layers)
             .add_systems(
                 Render,
-                prepare_fog_settings.in_set(RenderSet::PrepareResources),
+                (
+                    queue_fog.in_set(RenderSet::Queue),
+                    sort_unfogged_items
+                        .in_set(RenderSet::PhaseSort)
+                        .after(sort_phase_system::<Transparent2d>),
+                    prepare_fog_settings.in_set(RenderSet::PrepareResources),
+                    prepare_fog_bind_groups.in_set(RenderSet::PrepareBindGroups),
+                ),
             )
-            .add_render_graph_node::<ViewNodeRunner<FogNode2d>>(Core2d, FogNode2dLabel)
             .add_render_graph_node::<ViewNodeRunner<VisionComputeNode>>(Core2d, VisionComputeLabel)
             .add_render_graph_edges(
                 Core2d,
                 (
-                    Node2d::MainTransparentPass,
+                    Node2d::StartMainPass,
                     VisionComputeLabel,
-                    FogNode2dLabel,
-                    Node2d::EndMainPass,
+                    Node2d::MainOpaquePass,
                 ),
             );
     }
@@ -0,0 +1,359 @@
+use crate::VISIBILITY_TEXTURE_SIZE;
+use crate::chunk::{ChunkCoord, FogChunkManager};
+use crate::vision_compute::VisibilityTextureResource;
+use alloc::sync::Arc;
+use bevy::asset::RenderAssetUsages;
+use bevy::image::IntoDynamicImageError;
+use bevy::prelude::*;
+use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
+use bevy::render::render_resource::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageDataLayout, Maintain,
+    MapMode, TextureDimension, TextureFormat,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::{Render, RenderApp, RenderSet};
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use image::ImageError;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// 掩码中未探索区块的灰度值
+/// Gray value of unexplored chunks in a mask
+pub const MASK_UNEXPLORED: u8 = 0;
+/// 掩码中已探索但不可见区块的灰度值
+/// Gray value of explored but not visible chunks in a mask
+pub const MASK_EXPLORED: u8 = 128;
+/// 掩码中可见区块的灰度值
+/// Gray value of visible chunks in a mask
+pub const MASK_VISIBLE: u8 = 255;
+
+/// 迷雾掩码错误
+/// Fog mask error
+#[derive(Debug)]
+pub enum FogMaskError {
+    /// 图像格式无法转换为灰度
+    /// The image format cannot be converted to grayscale
+    UnsupportedImage(IntoDynamicImageError),
+    /// PNG 读写失败
+    /// Reading or writing the PNG failed
+    Png(ImageError),
+}
+
+impl fmt::Display for FogMaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedImage(error) => write!(f, "unsupported fog mask image: {error}"),
+            Self::Png(error) => write!(f, "fog mask png error: {error}"),
+        }
+    }
+}
+
+impl core::error::Error for FogMaskError {}
+
+impl From<IntoDynamicImageError> for FogMaskError {
+    fn from(error: IntoDynamicImageError) -> Self {
+        Self::UnsupportedImage(error)
+    }
+}
+
+impl From<ImageError> for FogMaskError {
+    fn from(error: ImageError) -> Self {
+        Self::Png(error)
+    }
+}
+
+/// 导出区块可见性掩码，每个像素对应一个区块
+/// Export the chunk visibility mask, one pixel per chunk
+///
+/// `origin` 是左下角区块；图像第一行对应 y 最大的区块
+/// `origin` is the bottom-left chunk; the first image row holds the chunks with the greatest y
+pub fn export_chunk_mask(manager: &FogChunkManager, origin: ChunkCoord, size: UVec2) -> Image {
+    let mut data = Vec::with_capacity((size.x * size.y) as usize);
+    for row in 0..size.y {
+        for column in 0..size.x {
+            let coord = mask_pixel_to_chunk(origin, size, column, row);
+            data.push(if manager.visible_chunks.contains(&coord) {
+                MASK_VISIBLE
+            } else if manager.explored_chunks.contains(&coord) {
+                MASK_EXPLORED
+            } else {
+                MASK_UNEXPLORED
+            });
+        }
+    }
+
+    gray_image(size, data)
+}
+
+/// 导入已探索掩码，亮度不低于 [`MASK_EXPLORED`] 的像素标记为已探索
+/// Import an explored mask; pixels at least as bright as [`MASK_EXPLORED`] are marked explored
+///
+/// 接受任意可转换为灰度的图像，例如通过 `AssetServer` 加载的 PNG，返回新探索的区块数
+/// Accepts any image convertible to grayscale, such as a PNG loaded via `AssetServer`; returns the number of newly explored chunks
+pub fn import_explored_mask(
+    manager: &mut FogChunkManager,
+    mask: &Image,
+    origin: ChunkCoord,
+) -> Result<usize, FogMaskError> {
+    let gray = mask.clone().try_into_dynamic()?.to_luma8();
+    let size = UVec2::new(gray.width(), gray.height());
+
+    let chunks: Vec<ChunkCoord> = gray
+        .enumerate_pixels()
+        .filter(|(.., pixel)| pixel.0[0] >= MASK_EXPLORED)
+        .map(|(column, row, _)| mask_pixel_to_chunk(origin, size, column, row))
+        .filter(|coord| !manager.explored_chunks.contains(coord))
+        .collect();
+
+    // 通过 explore 标记，已存在的区块实体会在下次区块更新时刷新
+    // Marked through explore so existing chunk entities are refreshed on the next chunk update
+    let explored = chunks.len();
+    manager.explore(chunks);
+    Ok(explored)
+}
+
+/// 将灰度掩码保存为 PNG
+/// Save a grayscale mask as PNG
+pub fn save_mask_png(mask: &Image, path: impl AsRef<Path>) -> Result<(), FogMaskError> {
+    mask.clone()
+        .try_into_dynamic()?
+        .to_luma8()
+        .save_with_format(path, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+/// 从 PNG 加载灰度掩码
+/// Load a grayscale mask from PNG
+pub fn load_mask_png(path: impl AsRef<Path>) -> Result<Image, FogMaskError> {
+    let gray = image::open(path)?.to_luma8();
+    Ok(gray_image(
+        UVec2::new(gray.width(), gray.height()),
+        gray.into_raw(),
+    ))
+}
+
+fn mask_pixel_to_chunk(origin: ChunkCoord, size: UVec2, column: u32, row: u32) -> ChunkCoord {
+    ChunkCoord {
+        x: origin.x + column as i32,
+        y: origin.y + (size.y - 1 - row) as i32,
+    }
+}
+
+fn gray_image(size: UVec2, data: Vec<u8>) -> Image {
+    Image::new(
+        Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::all(),
+    )
+}
+
+/// GPU 可见性纹理快照
+/// GPU visibility texture snapshot
+///
+/// 调用 [`FogVisibilitySnapshot::request`] 后，渲染世界会在下一帧复制可见性纹理并异步读回，
+/// 读回完成后（通常在数帧之后）可通过 [`FogVisibilitySnapshot::take`] 取得 `R8Unorm` 灰度图像
+/// After [`FogVisibilitySnapshot::request`], the render world copies the visibility texture on the next frame and reads it
+/// back asynchronously; once done, usually a few frames later, [`FogVisibilitySnapshot::take`] yields an `R8Unorm` grayscale image
+#[derive(Resource, Clone, Default, ExtractResource)]
+pub struct FogVisibilitySnapshot(Arc<Mutex<SnapshotState>>);
+
+#[derive(Default)]
+struct SnapshotState {
+    requested: bool,
+    image: Option<Image>,
+}
+
+impl FogVisibilitySnapshot {
+    /// 请求读回可见性纹理
+    /// Request a readback of the visibility texture
+    pub fn request(&self) {
+        self.0.lock().unwrap().requested = true;
+    }
+
+    /// 取出已完成的快照
+    /// Take the finished snapshot
+    pub fn take(&self) -> Option<Image> {
+        self.0.lock().unwrap().image.take()
+    }
+}
+
+/// 读回缓冲区映射回调写入的结果
+/// Result written by the mapping callback of the readback buffer
+type MappingResult = Arc<Mutex<Option<Result<(), BufferAsyncError>>>>;
+
+/// 可见性纹理读回缓冲区
+/// Visibility texture readback buffer
+#[derive(Resource, Default)]
+pub struct VisibilityReadback {
+    buffer: Option<Buffer>,
+    copy_this_frame: bool,
+    /// 迷雾节点本帧是否已复制可见性纹理 (Whether the fog node copied the visibility texture this frame)
+    copied: AtomicBool,
+    /// 正在映射的读回缓冲区，映射完成时写入结果
+    /// Readback buffer being mapped, receiving the result once mapping completes
+    mapping: Option<MappingResult>,
+}
+
+impl VisibilityReadback {
+    // Rgba16Float 每像素 8 字节，1024 像素的行已满足 256 字节对齐
+    // Rgba16Float is 8 bytes per pixel, so a 1024 pixel row already meets the 256 byte alignment
+    const BYTES_PER_ROW: u32 = VISIBILITY_TEXTURE_SIZE * 8;
+
+    /// 若本帧请求了快照，则将可见性纹理复制到读回缓冲区
+    /// Copy the visibility texture into the readback buffer if a snapshot was requested this frame
+    pub fn copy_visibility_texture(&self, render_context: &mut RenderContext, world: &World) {
+        if !self.copy_this_frame {
+            return;
+        }
+        let (Some(buffer), Some(visibility_texture)) = (
+            &self.buffer,
+            &world.resource::<VisibilityTextureResource>().texture,
+        ) else {
+            return;
+        };
+
+        render_context.command_encoder().copy_texture_to_buffer(
+            visibility_texture.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(Self::BYTES_PER_ROW),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: VISIBILITY_TEXTURE_SIZE,
+                height: VISIBILITY_TEXTURE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.copied.store(true, Ordering::Release);
+    }
+}
+
+/// 处理快照请求，准备读回缓冲区
+/// Handle snapshot requests and prepare the readback buffer
+pub fn prepare_visibility_readback(
+    snapshot: Res<FogVisibilitySnapshot>,
+    render_device: Res<RenderDevice>,
+    mut readback: ResMut<VisibilityReadback>,
+) {
+    // 上一次读回仍在映射时，请求保留到缓冲区空闲；请求在纹理实际复制后才清除，没有迷雾视图渲染时留到下一帧
+    // While the previous readback is still mapping, the request is kept until the buffer is free; it is only cleared
+    // once the texture was actually copied, staying pending while no fog view renders
+    if !snapshot.0.lock().unwrap().requested || readback.mapping.is_some() {
+        return;
+    }
+
+    if readback.buffer.is_none() {
+        readback.buffer = Some(render_device.create_buffer(&BufferDescriptor {
+            label: Some("visibility_readback_buffer"),
+            size: (VisibilityReadback::BYTES_PER_ROW * VISIBILITY_TEXTURE_SIZE) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        }));
+    }
+    readback.copy_this_frame = true;
+}
+
+/// 在渲染提交后异步映射读回缓冲区，映射完成后的某一帧生成灰度快照
+/// Map the readback buffer asynchronously after the render submission, building the grayscale snapshot on a later frame once mapped
+pub fn map_visibility_readback(
+    snapshot: Res<FogVisibilitySnapshot>,
+    render_device: Res<RenderDevice>,
+    mut readback: ResMut<VisibilityReadback>,
+) {
+    let Some(buffer) = readback.buffer.clone() else {
+        return;
+    };
+
+    // 迷雾节点没有运行时缓冲区内容已过时，不映射
+    // The buffer contents are stale when the fog node did not run, so nothing is mapped
+    if core::mem::take(&mut readback.copy_this_frame)
+        && readback.copied.swap(false, Ordering::Acquire)
+    {
+        snapshot.0.lock().unwrap().requested = false;
+        let mapping = Arc::new(Mutex::new(None));
+        let result = mapping.clone();
+        buffer.slice(..).map_async(MapMode::Read, move |mapped| {
+            *result.lock().unwrap() = Some(mapped);
+        });
+        readback.mapping = Some(mapping);
+    }
+
+    let Some(mapping) = &readback.mapping else {
+        return;
+    };
+    // 不阻塞渲染线程，只处理已完成的映射回调
+    // Do not block the render thread, only handle mapping callbacks that already completed
+    render_device.poll(Maintain::Poll);
+    let Some(result) = mapping.lock().unwrap().take() else {
+        return;
+    };
+    readback.mapping = None;
+    if let Err(error) = result {
+        error!("Failed to map the visibility readback buffer: {error}");
+        return;
+    }
+
+    let data = {
+        let mapped = buffer.slice(..).get_mapped_range();
+        let channels: &[u16] = bytemuck::cast_slice(&mapped);
+        // 可见性存储在 A 通道
+        // Visibility is stored in the A channel
+        channels
+            .chunks_exact(4)
+            .map(|pixel| (f16_to_f32(pixel[3]).clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    };
+    buffer.unmap();
+
+    snapshot.0.lock().unwrap().image =
+        Some(gray_image(UVec2::splat(VISIBILITY_TEXTURE_SIZE), data));
+}
+
+/// 将半精度浮点位转换为 f32
+/// Convert half precision float bits to f32
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// 迷雾掩码导入导出插件
+/// Fog mask import and export plugin
+pub struct FogMaskPlugin;
+
+impl Plugin for FogMaskPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FogVisibilitySnapshot>()
+            .add_plugins(ExtractResourcePlugin::<FogVisibilitySnapshot>::default());
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .init_resource::<VisibilityReadback>()
+            .add_systems(
+                Render,
+                (
+                    prepare_visibility_readback.in_set(RenderSet::PrepareResources),
+                    map_visibility_readback.in_set(RenderSet::Cleanup),
+                ),
+            );
+    }
+}
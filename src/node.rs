@@ -1,38 +1,52 @@
 use crate::FOG_2D_SHADER_HANDLE;
-use crate::fog::{FogOfWarMeta, GpuFogMaterial, ViewFogOfWarUniformOffset};
+use crate::layer::ExtractedFogLayers;
+use crate::fog::{
+    FogMaterial, FogOfWarMeta, FogRenderMode, GpuFogMaterial, ViewFogOfWarUniformOffset,
+};
+use crate::vision_compute::VisibilityTextureResource;
 use bevy::{
     asset::{AssetServer, Handle},
-    core_pipeline::fullscreen_vertex_shader::fullscreen_shader_vertex_state,
-    ecs::{query::QueryItem, system::lifetimeless::Read},
-    prelude::{ FromWorld, Image, Resource, World},
+    core_pipeline::{
+        core_2d::{CORE_2D_DEPTH_FORMAT, Transparent2d},
+        fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+    },
+    ecs::{
+        query::ROQueryItem,
+        system::{SystemParamItem, lifetimeless::Read},
+    },
+    image::BevyDefault,
+    math::FloatOrd,
+    prelude::{
+        Changed, Commands, Component, Entity, FromWorld, Image, Msaa, Query, RemovedComponents,
+        Res, ResMut, Resource, With, World,
+    },
     render::{
+        Extract,
         render_asset::RenderAssets,
-        render_graph::{NodeRunError, RenderGraphContext, RenderLabel, ViewNode},
+        render_phase::{
+            DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand, RenderCommandResult,
+            SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
         render_resource::{
-            BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries, BindGroupLayoutEntry,
-            BindingResource, BindingType, BlendComponent, BlendState, CachedRenderPipelineId,
-            ColorTargetState, ColorWrites, Extent3d, FragmentState, FrontFace, LoadOp,
-            MultisampleState, Operations, PipelineCache, PolygonMode, PrimitiveState,
-            PrimitiveTopology, RenderPassColorAttachment, RenderPassDescriptor,
-            RenderPipelineDescriptor, ShaderStages, StoreOp, TextureDescriptor, TextureDimension,
-            TextureFormat, TextureUsages, binding_types::uniform_buffer,
+            BindGroup, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
+            BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendState,
+            ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState,
+            Extent3d, FragmentState, FrontFace, MultisampleState, PipelineCache, PolygonMode,
+            PrimitiveState, PrimitiveTopology, RenderPipelineDescriptor, ShaderStages,
+            SpecializedRenderPipeline, SpecializedRenderPipelines, StencilState, TextureDescriptor,
+            TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+            binding_types::{texture_2d, uniform_buffer},
         },
-        renderer::{RenderContext, RenderDevice},
-        texture::GpuImage,
-        view::{ViewTarget, ViewUniforms},
+        renderer::RenderDevice,
+        sync_world::{MainEntity, MainEntityHashMap},
+        texture::{FallbackImage, GpuImage},
+        view::{ExtractedView, RenderLayers, ViewTarget},
     },
-    utils::default,
 };
 
-/// 迷雾节点名称
-/// Fog node name
-#[derive(RenderLabel, Debug, Clone, Hash, PartialEq, Eq)]
-pub struct FogNode2dLabel;
-
 #[derive(Resource)]
 pub struct FogOfWar2dPipeline {
     pub bind_group_layout: BindGroupLayout,
-    pub pipeline_id: CachedRenderPipelineId,
     pub noise_texture: Option<Handle<Image>>,
     // 添加对可见性纹理的引用
     // Add reference to visibility texture
@@ -110,61 +124,19 @@ impl FromWorld for FogOfWar2dPipeline {
                         binding: u32::MAX,
                         visibility: ShaderStages::FRAGMENT,
                     },
+                    // 外观图：颜色与密度、噪声强度
+                    // Appearance map: color and density, noise intensity
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    texture_2d(TextureSampleType::Float { filterable: true }),
+                    // 已探索纹理
+                    // Explored texture
+                    texture_2d(TextureSampleType::Float { filterable: true }),
                 ),
             ),
         );
 
-        let pipeline_id = world.resource_mut::<PipelineCache>().queue_render_pipeline(
-            RenderPipelineDescriptor {
-                label: Some("fog_of_war_2d_pipeline".into()),
-                layout: vec![bind_group_layout.clone()],
-                vertex: fullscreen_shader_vertex_state(),
-                fragment: Some(FragmentState {
-                    shader: FOG_2D_SHADER_HANDLE,
-                    shader_defs: vec![],
-                    entry_point: "fragment".into(),
-                    targets: vec![Some(ColorTargetState {
-                        format: TextureFormat::Rgba8UnormSrgb, // 明确指定格式
-                        blend: Some(BlendState {
-                            color: BlendComponent {
-                                src_factor: bevy::render::render_resource::BlendFactor::SrcAlpha,
-                                dst_factor:
-                                    bevy::render::render_resource::BlendFactor::OneMinusSrcAlpha,
-                                operation: bevy::render::render_resource::BlendOperation::Add,
-                            },
-                            alpha: BlendComponent {
-                                src_factor: bevy::render::render_resource::BlendFactor::SrcAlpha,
-                                dst_factor:
-                                    bevy::render::render_resource::BlendFactor::OneMinusSrcAlpha,
-                                operation: bevy::render::render_resource::BlendOperation::Add,
-                            },
-                        }),
-                        write_mask: ColorWrites::ALL,
-                    })],
-                }),
-                primitive: PrimitiveState {
-                    topology: PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: FrontFace::Ccw,
-                    cull_mode: None,
-                    unclipped_depth: false,
-                    polygon_mode: PolygonMode::Fill,
-                    conservative: false,
-                },
-                depth_stencil: None,
-                multisample: MultisampleState {
-                    count: 1,
-                    mask: !0,
-                    alpha_to_coverage_enabled: false,
-                },
-                push_constant_ranges: vec![],
-                zero_initialize_workgroup_memory: false,
-            },
-        );
-
         Self {
             bind_group_layout,
-            pipeline_id,
             noise_texture: Some(noise_texture),
             // explored_texture: Some(explored_texture),
             // texture: Some(texture),
@@ -172,108 +144,341 @@ impl FromWorld for FogOfWar2dPipeline {
     }
 }
 
-#[derive(Default)]
-pub struct FogNode2d;
-
-impl ViewNode for FogNode2d {
-    type ViewQuery = (Read<ViewTarget>, Read<ViewFogOfWarUniformOffset>);
-
-    fn run(
-        &self,
-        _graph: &mut RenderGraphContext,
-        render_context: &mut RenderContext,
-        (view_target, view_fog_offset): QueryItem<Self::ViewQuery>,
-        world: &World,
-    ) -> Result<(), NodeRunError> {
-        let pipeline_cache = world.resource::<PipelineCache>();
-        let fog_of_war_pipeline = world.resource::<FogOfWar2dPipeline>();
-        let view_uniforms = world.resource::<ViewUniforms>();
-
-        let Some(view_uniforms_binding) = view_uniforms.uniforms.binding() else {
-            return Ok(());
+/// 迷雾管线特化键
+/// Fog pipeline specialization key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FogOfWar2dPipelineKey {
+    /// 视图是否使用 HDR 主纹理
+    /// Whether the view renders to an HDR main texture
+    pub hdr: bool,
+    /// 迷雾在透明通道内绘制，需要匹配其 MSAA 采样数
+    /// Fog is drawn inside the transparent pass and must match its MSAA sample count
+    pub msaa_samples: u32,
+}
+
+impl SpecializedRenderPipeline for FogOfWar2dPipeline {
+    type Key = FogOfWar2dPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let format = if key.hdr {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        // 透明通道带有深度附件，迷雾图层不读写深度，只依赖排序
+        // The transparent pass has a depth attachment; the fog layer ignores depth and relies on sorting
+        let depth_stencil = Some(DepthStencilState {
+            format: CORE_2D_DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: CompareFunction::Always,
+            stencil: StencilState::default(),
+            bias: DepthBiasState::default(),
+        });
+
+        RenderPipelineDescriptor {
+            label: Some("fog_of_war_2d_pipeline".into()),
+            layout: vec![self.bind_group_layout.clone()],
+            vertex: fullscreen_shader_vertex_state(),
+            fragment: Some(FragmentState {
+                shader: FOG_2D_SHADER_HANDLE,
+                shader_defs: vec![],
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState {
+                        // 着色器输出预乘颜色，以便在可见区域叠加视野色调
+                        // The shader outputs premultiplied color so vision tints can be added in revealed areas
+                        color: BlendComponent {
+                            src_factor: bevy::render::render_resource::BlendFactor::One,
+                            dst_factor:
+                                bevy::render::render_resource::BlendFactor::OneMinusSrcAlpha,
+                            operation: bevy::render::render_resource::BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: bevy::render::render_resource::BlendFactor::One,
+                            dst_factor:
+                                bevy::render::render_resource::BlendFactor::OneMinusSrcAlpha,
+                            operation: bevy::render::render_resource::BlendOperation::Add,
+                        },
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Ccw,
+                cull_mode: None,
+                unclipped_depth: false,
+                polygon_mode: PolygonMode::Fill,
+                conservative: false,
+            },
+            depth_stencil,
+            multisample: MultisampleState {
+                count: key.msaa_samples,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            push_constant_ranges: vec![],
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+/// 视图的迷雾绑定组
+/// Fog bind group of a view
+#[derive(Component)]
+pub struct ViewFogBindGroup(pub BindGroup);
+
+/// 特化迷雾管线，并将迷雾加入透明阶段
+/// Specialize fog pipelines and queue the fog into the transparent phase
+pub fn queue_fog(
+    pipeline_cache: Res<PipelineCache>,
+    fog_pipeline: Res<FogOfWar2dPipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<FogOfWar2dPipeline>>,
+    draw_functions: Res<DrawFunctions<Transparent2d>>,
+    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent2d>>,
+    views: Query<(Entity, &MainEntity, &ExtractedView, &Msaa, &FogMaterial)>,
+) {
+    let draw_fog = draw_functions.read().id::<DrawFog2d>();
+
+    for (view_entity, main_entity, view, msaa, fog_material) in &views {
+        let Some(transparent_phase) = transparent_render_phases.get_mut(&view_entity) else {
+            continue;
         };
 
-        let Some(pipeline) = pipeline_cache.get_render_pipeline(fog_of_war_pipeline.pipeline_id)
-        else {
-            return Ok(());
+        let key = FogOfWar2dPipelineKey {
+            hdr: view.hdr,
+            msaa_samples: msaa.samples(),
         };
+        let pipeline = pipelines.specialize(&pipeline_cache, &fog_pipeline, key);
 
-        let Some(settings_binding) = world.resource::<FogOfWarMeta>().gpu_fog_settings.binding()
-        else {
-            return Ok(());
+        // 叠加模式排在所有受迷雾覆盖的阶段项之后
+        // Overlay mode sorts after every fogged phase item
+        let z = match fog_material.render_mode {
+            FogRenderMode::Overlay => f32::INFINITY,
+            FogRenderMode::WorldSpace { z } => z,
         };
 
-        let view = view_target.main_texture_view();
+        // 迷雾以视图实体作为阶段项实体，与精灵一起按 z 排序
+        // The fog uses the view entity as its phase item entity and is sorted with sprites by z
+        transparent_phase.add(Transparent2d {
+            sort_key: FloatOrd(z),
+            entity: (view_entity, *main_entity),
+            pipeline,
+            draw_function: draw_fog,
+            batch_range: 0..1,
+            extra_index: PhaseItemExtraIndex::NONE,
+        });
+    }
+}
 
-        // 获取噪声纹理和采样器
-        // Get noise texture and sampler
-        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
-        let noise_texture_id = fog_of_war_pipeline.noise_texture.as_ref().unwrap();
-        let fallback_image = world.resource::<bevy::render::texture::FallbackImage>();
+/// 从主世界提取的实体渲染层
+/// Render layers extracted from main world entities
+#[derive(Resource, Default)]
+pub struct ExtractedFogRenderLayers(pub MainEntityHashMap<RenderLayers>);
 
-        // 获取噪声纹理或使用回退图像
-        // Get noise texture or use fallback image
-        let noise_texture_view = if let Some(gpu_image) = gpu_images.get(noise_texture_id) {
-            &gpu_image.texture_view
-        } else {
-            &fallback_image.d2.texture_view
+/// 本帧移除了渲染层或被销毁的主世界实体
+/// Main world entities whose render layers were removed or that were despawned this frame
+#[derive(Resource, Default)]
+pub struct RemovedFogRenderLayers(pub Vec<Entity>);
+
+/// 收集本帧移除的渲染层，供提取阶段使用
+/// Collect the render layers removed this frame for the extract schedule
+pub fn collect_removed_render_layers(
+    mut removed: RemovedComponents<RenderLayers>,
+    mut removed_layers: ResMut<RemovedFogRenderLayers>,
+) {
+    removed_layers.0.clear();
+    removed_layers.0.extend(removed.read());
+}
+
+/// 增量提取实体渲染层，用于判断阶段项是否受迷雾覆盖
+/// Incrementally extract entity render layers to decide whether phase items are fogged
+pub fn extract_fog_render_layers(
+    mut extracted: ResMut<ExtractedFogRenderLayers>,
+    changed: Extract<Query<(Entity, &RenderLayers), Changed<RenderLayers>>>,
+    removed: Extract<Res<RemovedFogRenderLayers>>,
+) {
+    for entity in &removed.0 {
+        extracted.0.remove(&MainEntity::from(*entity));
+    }
+    extracted.0.extend(
+        changed
+            .iter()
+            .map(|(entity, layers)| (MainEntity::from(entity), layers.clone())),
+    );
+}
+
+/// 将不受迷雾覆盖的阶段项移到迷雾之后绘制
+/// Move phase items that are not fogged behind the fog
+///
+/// 在排序之后、合批之前运行，保持各组内部的 z 顺序
+/// Runs after sorting and before batching, keeping the z order within each group
+pub fn sort_unfogged_items(
+    render_layers: Res<ExtractedFogRenderLayers>,
+    mut transparent_render_phases: ResMut<ViewSortedRenderPhases<Transparent2d>>,
+    views: Query<(Entity, &FogMaterial)>,
+) {
+    if render_layers.0.is_empty() {
+        return;
+    }
+
+    let default_layers = RenderLayers::default();
+    for (view_entity, fog_material) in &views {
+        let Some(transparent_phase) = transparent_render_phases.get_mut(&view_entity) else {
+            continue;
         };
 
-        let sampler = &world
-            .resource::<bevy::render::texture::FallbackImage>()
-            .d2
-            .sampler;
-
-        // 获取可见性纹理（从VisibilityTextureResource资源中）
-        // Get visibility texture (from VisibilityTextureResource resource)
-        let visibility_texture_view = if let Some(visibility_texture_resource) = world.get_resource::<crate::vision_compute::VisibilityTextureResource>() {
-            if let Some(visibility_texture) = &visibility_texture_resource.texture {
-                &visibility_texture.default_view
-            } else {
-                // 如果可见性纹理不可用，使用回退图像
-                // If visibility texture is not available, use fallback image
-                &fallback_image.d2.texture_view
-            }
-        } else {
-            // 如果找不到VisibilityTextureResource，使用回退图像
-            // If VisibilityTextureResource is not found, use fallback image
-            &fallback_image.d2.texture_view
+        // 没有渲染层的实体位于默认层；迷雾阶段项本身总是受覆盖的一组
+        // Entities without render layers live on the default layer; the fog item itself is always in the fogged group
+        let is_fogged = |item: &Transparent2d| {
+            item.entity() == view_entity
+                || render_layers
+                    .0
+                    .get(&item.main_entity())
+                    .unwrap_or(&default_layers)
+                    .intersects(&fog_material.fogged_layers)
         };
 
-        let bind_group = render_context.render_device().create_bind_group(
-            None,
-            &fog_of_war_pipeline.bind_group_layout,
+        if transparent_phase.items.iter().all(is_fogged) {
+            continue;
+        }
+
+        let (fogged, unfogged): (Vec<_>, Vec<_>) =
+            transparent_phase.items.drain(..).partition(is_fogged);
+        transparent_phase.items.extend(fogged);
+        transparent_phase.items.extend(unfogged);
+    }
+}
+
+/// 准备迷雾绑定组
+/// Prepare fog bind groups
+#[allow(clippy::too_many_arguments)]
+pub fn prepare_fog_bind_groups(
+    mut commands: Commands,
+    render_device: Res<RenderDevice>,
+    fog_pipeline: Res<FogOfWar2dPipeline>,
+    fog_meta: Res<FogOfWarMeta>,
+    gpu_images: Res<RenderAssets<GpuImage>>,
+    fallback_image: Res<FallbackImage>,
+    visibility_texture_resource: Res<VisibilityTextureResource>,
+    fog_layers: Res<ExtractedFogLayers>,
+    views: Query<(Entity, &FogMaterial), With<ViewFogOfWarUniformOffset>>,
+) {
+    let Some(settings_binding) = fog_meta.gpu_fog_settings.binding() else {
+        return;
+    };
+
+    // 获取噪声纹理或使用回退图像
+    // Get noise texture or use fallback image
+    let noise_texture_view = fog_pipeline
+        .noise_texture
+        .as_ref()
+        .and_then(|handle| gpu_images.get(handle))
+        .map_or(&fallback_image.d2.texture_view, |gpu_image| {
+            &gpu_image.texture_view
+        });
+
+    // 如果可见性纹理不可用，使用回退图像
+    // If visibility texture is not available, use fallback image
+    let visibility_texture_view = visibility_texture_resource
+        .texture
+        .as_ref()
+        .map_or(&fallback_image.d2.texture_view, |texture| {
+            &texture.default_view
+        });
+
+    for (view_entity, fog_material) in &views {
+        // 绑定视图所选迷雾层的已探索纹理，未生成时使用回退图像
+        // Bind the explored texture of the view's fog layer, using the fallback image until it is built
+        let explored_texture_view = fog_layers
+            .explored_texture(fog_material.layer)
+            .image
+            .as_ref()
+            .and_then(|handle| gpu_images.get(handle))
+            .map_or(&fallback_image.d2.texture_view, |gpu_image| {
+                &gpu_image.texture_view
+            });
+
+        // 外观图按视图绑定，未加载时使用回退图像
+        // Appearance maps are bound per view, using the fallback image until loaded
+        let appearance = fog_material.appearance.as_ref();
+        let appearance_view = |handle: Option<&Handle<Image>>| {
+            handle
+                .and_then(|handle| gpu_images.get(handle))
+                .map_or(&fallback_image.d2.texture_view, |gpu_image| {
+                    &gpu_image.texture_view
+                })
+        };
+        let color_density_view = appearance_view(appearance.map(|map| &map.color_density));
+        let noise_intensity_view =
+            appearance_view(appearance.and_then(|map| map.noise_intensity.as_ref()));
+
+        let bind_group = render_device.create_bind_group(
+            "fog_of_war_bind_group",
+            &fog_pipeline.bind_group_layout,
             &BindGroupEntries::sequential((
-                // view_uniforms_binding,
                 settings_binding.clone(),
                 // 添加噪声纹理和采样器绑定
                 // Add noise texture and sampler bindings
                 BindingResource::TextureView(noise_texture_view),
-                BindingResource::Sampler(sampler),
+                BindingResource::Sampler(&fallback_image.d2.sampler),
                 // 添加可见性纹理绑定
                 // Add visibility texture binding
                 BindingResource::TextureView(visibility_texture_view),
-                
+                BindingResource::TextureView(color_density_view),
+                BindingResource::TextureView(noise_intensity_view),
+                BindingResource::TextureView(explored_texture_view),
             )),
         );
 
-        let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
-            label: Some("fog_of_war_2d_pass"),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view,
-                resolve_target: None,
-                ops: Operations {
-                    load: LoadOp::Load,
-                    store: StoreOp::Store,
-                },
-            })],
-            ..default()
-        });
+        commands
+            .entity(view_entity)
+            .insert(ViewFogBindGroup(bind_group));
+    }
+}
+
+/// 世界空间迷雾的绘制命令
+/// Draw commands for world-space fog
+pub type DrawFog2d = (SetItemPipeline, SetFogBindGroup<0>, DrawFogTriangle);
+
+pub struct SetFogBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetFogBindGroup<I> {
+    type Param = ();
+    type ViewQuery = (Read<ViewFogBindGroup>, Read<ViewFogOfWarUniformOffset>);
+    type ItemQuery = ();
+
+    fn render<'w>(
+        _item: &P,
+        (bind_group, view_fog_offset): ROQueryItem<'w, Self::ViewQuery>,
+        _entity: Option<()>,
+        _param: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        pass.set_bind_group(I, &bind_group.0, &[view_fog_offset.offset]);
+        RenderCommandResult::Success
+    }
+}
+
+pub struct DrawFogTriangle;
 
-        render_pass.set_render_pipeline(pipeline);
-        render_pass.set_bind_group(0, &bind_group, &[view_fog_offset.offset]);
+impl<P: PhaseItem> RenderCommand<P> for DrawFogTriangle {
+    type Param = ();
+    type ViewQuery = ();
+    type ItemQuery = ();
 
-        render_pass.draw(0..3, 0..1);
-        Ok(())
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        _entity: Option<()>,
+        _param: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        // 全屏三角形 / Fullscreen triangle
+        pass.draw(0..3, 0..1);
+        RenderCommandResult::Success
     }
 }
@@ -1,5 +1,25 @@
 pub use crate::{
     ZingFogPlugins,
-    fog::{FogMaterial, FogOfWarConfig},
-    chunk::{ChunkCoord, ChunkVisibility, FogChunk, FogChunkManager, VisionProvider},
+    fog::{FogEdge, FogExploredStyle, FogMaterial, FogNoise, FogOfWarConfig, FogRenderMode},
+    mask::{
+        FogMaskError, FogVisibilitySnapshot, MASK_EXPLORED, MASK_UNEXPLORED, MASK_VISIBLE,
+        export_chunk_mask, import_explored_mask, load_mask_png, save_mask_png,
+    },
+    chunk::{
+        ChunkCoord, ChunkLayout, ChunkVisibility, FogChunk, FogChunkManager, FogExploredTexture,
+        VisionProvider,
+    },
+    environment::{VisionEnvironment, VisionZone},
+    appearance::{FogAppearance, FogAppearanceMap},
+    bitset::{CHUNK_PAGE_SIZE, ChunkBitSet},
+    commands::{FogCommands, FogLayerCommands, FogShape},
+    reveal::TemporaryReveal,
+    level::{FogLevels, FogStatePlugin},
+    layer::{FogLayer, InFogLayer},
+    ghost::{FogGhost, LeavesGhost},
+    sight::{EntityLostSight, EntitySpotted, FogTarget, FogTeam, Stealthed, TeamSight},
+    vision_layers::VisionLayers,
+    height::VisionHeightMap,
+    last_known::{LastKnownPosition, LastKnownPositions, TargetLastSeen},
+    stamp::{MAX_VISION_STAMPS, VISION_STAMP_SIZE, VisionStamps},
 };
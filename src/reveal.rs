@@ -0,0 +1,77 @@
+use crate::chunk::{VisionProvider, update_chunk_visibility};
+use bevy::math::curve::{Curve, EaseFunction, EasingCurve};
+use bevy::prelude::*;
+
+/// 临时揭示，例如扫描、照明弹和信号
+/// Temporary reveal, e.g. scans, flares and pings
+///
+/// 揭示的形状由同一实体上的 [`VisionProvider`] 决定；可见性按淡出曲线随时间减弱，到期后实体自动销毁
+/// The shape comes from the [`VisionProvider`] on the same entity; the visibility fades along the fade curve and the entity despawns itself once expired
+#[derive(Component, Reflect, Clone, Debug)]
+#[require(VisionProvider)]
+pub struct TemporaryReveal {
+    /// 持续时间（秒）
+    /// Duration in seconds
+    pub duration: f32,
+    /// 淡出曲线，在持续时间内从完全可见过渡到不可见
+    /// Fade curve, going from fully visible to invisible over the duration
+    pub fade: EaseFunction,
+    /// 已经过的时间（秒）
+    /// Elapsed time in seconds
+    elapsed: f32,
+}
+
+impl TemporaryReveal {
+    /// 创建持续 `duration` 秒的揭示，默认先保持明亮再迅速淡出
+    /// Create a reveal lasting `duration` seconds, staying bright first and then fading quickly by default
+    pub fn new(duration: f32) -> Self {
+        Self {
+            duration,
+            fade: EaseFunction::QuadraticIn,
+            elapsed: 0.0,
+        }
+    }
+
+    /// 剩余时间（秒）
+    /// Remaining time in seconds
+    pub fn remaining(&self) -> f32 {
+        (self.duration - self.elapsed).max(0.0)
+    }
+
+    /// 当前的揭示强度 (0.0-1.0)
+    /// Current reveal strength (0.0-1.0)
+    pub fn strength(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 0.0;
+        }
+        EasingCurve::new(1.0, 0.0, self.fade).sample_clamped(self.elapsed / self.duration)
+    }
+}
+
+/// 推进临时揭示的计时，销毁到期的揭示
+/// Advance temporary reveal timers and despawn expired reveals
+pub fn update_temporary_reveals(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut reveals: Query<(Entity, &mut TemporaryReveal)>,
+) {
+    for (entity, mut reveal) in &mut reveals {
+        reveal.elapsed += time.delta_secs();
+        if reveal.elapsed >= reveal.duration {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// 临时揭示插件
+/// Temporary reveal plugin
+pub struct TemporaryRevealPlugin;
+
+impl Plugin for TemporaryRevealPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<TemporaryReveal>().add_systems(
+            Update,
+            update_temporary_reveals.before(update_chunk_visibility),
+        );
+    }
+}
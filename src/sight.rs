@@ -0,0 +1,255 @@
+use crate::chunk::{ProviderSight, VisionProvider, update_chunk_visibility};
+use crate::environment::VisionEnvironment;
+use crate::fog::FogOfWarConfig;
+use crate::height::VisionHeightMap;
+use crate::layer::{FogLayer, InFogLayer};
+use crate::stamp::VisionStamps;
+use crate::vision_layers::VisionLayers;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+
+/// 视野提供者和目标所属的队伍，没有该组件的实体属于队伍 0
+/// Team of vision providers and targets; entities without it belong to team 0
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect)]
+pub struct FogTeam(pub u32);
+
+/// 被其他队伍观察的目标，例如敌方单位
+/// Target observed by other teams, e.g. an enemy unit
+///
+/// 目标按与 [`VisionProvider`] 相同的距离和印章规则逐个判断，而不是按区块
+/// Targets are tested one by one with the same distance and stamp rules as [`VisionProvider`], rather than per chunk
+///
+/// 目标进入或离开队伍视野时发送 [`EntitySpotted`] 和 [`EntityLostSight`]
+/// [`EntitySpotted`] and [`EntityLostSight`] are sent as targets enter or leave the sight of a team
+///
+/// 目标所在的视野层由 [`VisionLayers`] 组件决定，没有时位于地面
+/// The vision layer a target lives on comes from its [`VisionLayers`] component, ground when absent
+///
+/// [`FogOfWarConfig::viewer_team`] 在目标所在层上看不到的其他队伍目标会被隐藏，例如没有声呐时的潜艇，
+/// 即使目标所在的区块可见
+/// Targets of other teams the [`FogOfWarConfig::viewer_team`] does not see on their layer are hidden, e.g. submarines
+/// without sonar, even when their chunk is visible
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[require(Transform, Visibility)]
+pub struct FogTarget;
+
+/// 隐身目标，只有在敌方 [`VisionProvider::detection_range`] 内才可见，即使所在区块可见
+/// Stealthed target, only visible within an enemy [`VisionProvider::detection_range`], even when its chunk is visible
+#[derive(Component, Clone, Copy, Debug, Default, Reflect)]
+#[require(FogTarget)]
+pub struct Stealthed;
+
+/// 目标进入队伍视野的事件
+/// Event sent when a target enters the sight of a team
+#[derive(Event, Clone, Copy, Debug)]
+pub struct EntitySpotted {
+    pub observer_team: FogTeam,
+    pub target: Entity,
+}
+
+/// 目标离开队伍视野的事件，被销毁的目标不发送
+/// Event sent when a target leaves the sight of a team, not sent for despawned targets
+#[derive(Event, Clone, Copy, Debug)]
+pub struct EntityLostSight {
+    pub observer_team: FogTeam,
+    pub target: Entity,
+}
+
+/// 每支队伍当前看到的敌方目标
+/// Enemy targets each team currently sees
+#[derive(Resource, Default)]
+pub struct TeamSight {
+    seen: HashMap<FogTeam, HashSet<Entity>>,
+    /// 本帧离开视野的目标 (Targets that left sight this frame)
+    lost: Vec<(FogTeam, Entity)>,
+}
+
+impl TeamSight {
+    /// 队伍当前是否看到目标
+    /// Whether a team currently sees a target
+    pub fn is_seen(&self, team: FogTeam, target: Entity) -> bool {
+        self.seen
+            .get(&team)
+            .is_some_and(|seen| seen.contains(&target))
+    }
+
+    /// 队伍当前看到的目标
+    /// Targets a team currently sees
+    pub fn seen(&self, team: FogTeam) -> impl Iterator<Item = Entity> + '_ {
+        self.seen.get(&team).into_iter().flatten().copied()
+    }
+
+    /// 本帧离开队伍视野的目标，不包括被销毁的目标
+    /// Targets that left the sight of a team this frame, excluding despawned ones
+    pub fn lost(&self) -> impl Iterator<Item = (FogTeam, Entity)> + '_ {
+        self.lost.iter().copied()
+    }
+}
+
+/// 目标查询：实体、变换、队伍、视野层和是否隐身
+/// Target query: entity, transform, team, vision layers and whether stealthed
+type TargetQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static GlobalTransform,
+        Option<&'static FogTeam>,
+        Option<&'static VisionLayers>,
+        Has<Stealthed>,
+    ),
+    With<FogTarget>,
+>;
+
+/// 视野提供者查询：变换、视野、队伍和所属的迷雾层
+/// Vision provider query: transform, vision, team and fog layer
+type SightProviderQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static GlobalTransform,
+        &'static VisionProvider,
+        Option<&'static FogTeam>,
+        Option<&'static InFogLayer>,
+    ),
+>;
+
+/// 按视野提供者的视野更新每支队伍看到的目标，并发送进入和离开视野的事件
+/// Update the targets each team sees from the sight of its vision providers, sending events for targets entering and leaving sight
+///
+/// 每个提供者使用其所属迷雾层的 [`FogOfWarConfig`]，队伍的视野是其在所有层上视野的并集
+/// Each provider uses the [`FogOfWarConfig`] of its fog layer, and a team sees the union of its sight on every layer
+#[allow(clippy::too_many_arguments)]
+pub fn update_team_sight(
+    config: Res<FogOfWarConfig>,
+    layers: Query<&FogOfWarConfig, With<FogLayer>>,
+    environment: Res<VisionEnvironment>,
+    stamps: Res<VisionStamps>,
+    heights: Res<VisionHeightMap>,
+    providers: SightProviderQuery,
+    targets: TargetQuery,
+    mut sight: ResMut<TeamSight>,
+    mut spotted_events: EventWriter<EntitySpotted>,
+    mut lost_events: EventWriter<EntityLostSight>,
+) {
+    // 按队伍和迷雾层分组，提供者所属的层不存在时忽略
+    // Group by team and fog layer, ignoring providers whose layer does not exist
+    let mut groups: HashMap<_, (_, Vec<ProviderSight>)> = HashMap::new();
+    for (transform, vision, team, in_layer) in &providers {
+        let layer = in_layer.map(|in_layer| in_layer.0);
+        let config = match layer {
+            Some(layer) => match layers.get(layer) {
+                Ok(config) => config,
+                Err(_) => continue,
+            },
+            None => &*config,
+        };
+        groups
+            .entry((team.copied().unwrap_or_default(), layer))
+            .or_insert_with(|| (config, Vec::new()))
+            .1
+            .push(ProviderSight::new(
+                config,
+                &environment,
+                &stamps,
+                &heights,
+                transform,
+                vision,
+            ));
+    }
+
+    let mut seen: HashMap<FogTeam, HashSet<Entity>> = HashMap::new();
+    for (target, transform, target_team, layers, stealthed) in &targets {
+        let world_position = transform.translation().truncate();
+        let target_team = target_team.copied().unwrap_or_default();
+        let layers = layers.copied().unwrap_or_default();
+        for (&(team, _), (config, sights)) in &groups {
+            let position = config.to_fog(world_position);
+            // 队伍不观察自己的目标，隐身目标只能被探测到，提供者只看到自己视野层上的目标
+            // Teams do not observe their own targets, stealthed targets can only be detected, and
+            // providers only see targets on their vision layers
+            if team != target_team
+                && sights.iter().any(|sight| {
+                    if stealthed {
+                        sight.detects(position, layers)
+                    } else {
+                        sight.sees_on(position, layers)
+                    }
+                })
+            {
+                seen.entry(team).or_default().insert(target);
+            }
+        }
+    }
+
+    for (&observer_team, entities) in &seen {
+        for &target in entities {
+            if !sight.is_seen(observer_team, target) {
+                spotted_events.send(EntitySpotted {
+                    observer_team,
+                    target,
+                });
+            }
+        }
+    }
+
+    let previous = core::mem::replace(&mut sight.seen, seen);
+    sight.lost.clear();
+    for (observer_team, entities) in previous {
+        for target in entities {
+            if !sight.is_seen(observer_team, target) && targets.contains(target) {
+                sight.lost.push((observer_team, target));
+                lost_events.send(EntityLostSight {
+                    observer_team,
+                    target,
+                });
+            }
+        }
+    }
+}
+
+/// 隐藏观看队伍在目标所在层上看不到的目标，己方的目标不受影响
+/// Hide targets the viewer team does not see on their layer, leaving its own targets untouched
+///
+/// 只恢复由该系统隐藏的目标，游戏自行设置的 [`Visibility::Hidden`] 保持不变
+/// Only targets hidden by this system are restored, so a [`Visibility::Hidden`] set by the game itself is kept
+pub fn hide_unseen_targets(
+    config: Res<FogOfWarConfig>,
+    sight: Res<TeamSight>,
+    mut targets: Query<(Entity, Option<&FogTeam>, &mut Visibility), With<FogTarget>>,
+    mut hidden: Local<HashSet<Entity>>,
+) {
+    for (target, team, mut visibility) in &mut targets {
+        let visible = team.copied().unwrap_or_default() == config.viewer_team
+            || sight.is_seen(config.viewer_team, target);
+        if visible {
+            if hidden.remove(&target) {
+                *visibility = Visibility::Inherited;
+            }
+        } else if *visibility != Visibility::Hidden && hidden.insert(target) {
+            *visibility = Visibility::Hidden;
+        }
+    }
+    hidden.retain(|target| targets.contains(*target));
+}
+
+/// 队伍视野插件
+/// Team sight plugin
+pub struct FogSightPlugin;
+
+impl Plugin for FogSightPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<FogTeam>()
+            .register_type::<FogTarget>()
+            .register_type::<Stealthed>()
+            .init_resource::<TeamSight>()
+            .add_event::<EntitySpotted>()
+            .add_event::<EntityLostSight>()
+            .add_systems(
+                Update,
+                (update_team_sight, hide_unseen_targets)
+                    .chain()
+                    .after(update_chunk_visibility),
+            );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::chunk::{VisionProvider, update_chunk_visibility};
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+};
+use image::imageops::FilterType;
+use std::collections::HashMap;
+
+/// 视野印章在图集中的边长（像素）
+/// Side length of a vision stamp in the atlas (pixels)
+pub const VISION_STAMP_SIZE: u32 = 128;
+/// 视野印章图集的最大层数
+/// Maximum number of layers in the vision stamp atlas
+pub const MAX_VISION_STAMPS: usize = 16;
+/// 印章掩码达到该值时视为可见（CPU 区块判定）
+/// Stamp mask value at which a point counts as visible (CPU chunk test)
+pub const VISION_STAMP_THRESHOLD: f32 = 0.5;
+
+/// 视野印章图集
+/// Vision stamp atlas
+///
+/// 所有视野提供者使用的印章图像被重采样为 [`VISION_STAMP_SIZE`] 的灰度掩码（亮度乘以透明度），
+/// 并打包为纹理数组，供计算着色器和 CPU 区块可见性共同使用
+/// Stamp images used by vision providers are resampled to [`VISION_STAMP_SIZE`] grayscale masks (luminance times alpha)
+/// and packed into a texture array, shared by the compute shader and the CPU chunk visibility
+#[derive(Resource, Default)]
+pub struct VisionStamps {
+    /// 印章图像到图集层的映射 (Stamp image to atlas layer map)
+    layers: HashMap<AssetId<Image>, u32>,
+    /// 每层的 CPU 掩码 (CPU mask of each layer)
+    masks: Vec<Vec<u8>>,
+    /// 图集纹理 (Atlas texture)
+    pub atlas: Option<Handle<Image>>,
+}
+
+impl VisionStamps {
+    /// 印章图像所在的图集层，尚未加载时为 `None`
+    /// Atlas layer of a stamp image, `None` while it is not loaded yet
+    pub fn layer(&self, stamp: &Handle<Image>) -> Option<u32> {
+        self.layers.get(&stamp.id()).copied()
+    }
+
+    /// 在印章 UV 处采样掩码（最近邻），超出范围返回 0
+    /// Sample the mask at a stamp UV (nearest), returning 0 outside the stamp
+    pub fn sample(&self, layer: u32, uv: Vec2) -> f32 {
+        if uv.cmplt(Vec2::ZERO).any() || uv.cmpge(Vec2::ONE).any() {
+            return 0.0;
+        }
+        let Some(mask) = self.masks.get(layer as usize) else {
+            return 0.0;
+        };
+        let pixel = (uv * VISION_STAMP_SIZE as f32).as_uvec2();
+        mask[(pixel.y * VISION_STAMP_SIZE + pixel.x) as usize] as f32 / 255.0
+    }
+}
+
+/// 将相对视野提供者的偏移转换为印章 UV
+/// Convert an offset relative to the vision provider into stamp UV
+///
+/// 印章以提供者为中心，边长为两倍视野范围，并随提供者旋转；图像第一行位于上方
+/// The stamp is centered on the provider, twice the vision range wide and rotates with the provider; the first image row is at the top
+pub fn stamp_uv(offset: Vec2, rotation: f32, range: f32) -> Vec2 {
+    let local = Vec2::from_angle(-rotation).rotate(offset) / (2.0 * range);
+    Vec2::new(0.5 + local.x, 0.5 - local.y)
+}
+
+/// 视野提供者绕 Z 轴的旋转角度
+/// Rotation of a vision provider around the Z axis
+pub fn provider_rotation(transform: &GlobalTransform) -> f32 {
+    let right = transform.right();
+    right.y.atan2(right.x)
+}
+
+/// 印章图像变化时重建图集
+/// Rebuild the atlas when stamp images change
+pub fn update_vision_stamps(
+    mut stamps: ResMut<VisionStamps>,
+    mut images: ResMut<Assets<Image>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    providers: Query<&VisionProvider>,
+) {
+    let mut wanted: Vec<AssetId<Image>> = Vec::new();
+    for stamp in providers
+        .iter()
+        .filter_map(|provider| provider.stamp.as_ref())
+    {
+        if !wanted.contains(&stamp.id()) && images.contains(stamp) {
+            wanted.push(stamp.id());
+        }
+    }
+    if wanted.len() > MAX_VISION_STAMPS {
+        warn!(
+            "{} vision stamps in use, only the first {MAX_VISION_STAMPS} are applied",
+            wanted.len()
+        );
+        wanted.truncate(MAX_VISION_STAMPS);
+    }
+
+    let stamp_modified = image_events.read().any(|event| match event {
+        AssetEvent::Modified { id } => stamps.layers.contains_key(id),
+        _ => false,
+    });
+    let same_stamps = wanted.len() == stamps.layers.len()
+        && wanted.iter().all(|id| stamps.layers.contains_key(id));
+    if same_stamps && !stamp_modified {
+        return;
+    }
+
+    let mut layers = HashMap::new();
+    let mut masks = Vec::new();
+    for id in wanted {
+        let Some(mask) = images.get(id).and_then(stamp_mask) else {
+            warn!("Vision stamp {id} has an unsupported format, it is ignored");
+            continue;
+        };
+        layers.insert(id, masks.len() as u32);
+        masks.push(mask);
+    }
+
+    // 纹理数组至少需要一层
+    // A texture array needs at least one layer
+    let layer_count = masks.len().max(1) as u32;
+    let mut data = masks.concat();
+    data.resize(
+        (VISION_STAMP_SIZE * VISION_STAMP_SIZE * layer_count) as usize,
+        0,
+    );
+    let mut atlas = Image::new(
+        Extent3d {
+            width: VISION_STAMP_SIZE,
+            height: VISION_STAMP_SIZE,
+            depth_or_array_layers: layer_count,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    atlas.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+
+    match &stamps.atlas {
+        Some(handle) => images.insert(handle, atlas),
+        None => stamps.atlas = Some(images.add(atlas)),
+    }
+    stamps.layers = layers;
+    stamps.masks = masks;
+}
+
+fn stamp_mask(image: &Image) -> Option<Vec<u8>> {
+    let gray = image
+        .clone()
+        .try_into_dynamic()
+        .ok()?
+        .resize_exact(VISION_STAMP_SIZE, VISION_STAMP_SIZE, FilterType::Triangle)
+        .to_luma_alpha8();
+    Some(
+        gray.pixels()
+            .map(|pixel| ((pixel.0[0] as u16 * pixel.0[1] as u16) / 255) as u8)
+            .collect(),
+    )
+}
+
+/// 视野印章插件
+/// Vision stamp plugin
+pub struct VisionStampPlugin;
+
+impl Plugin for VisionStampPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VisionStamps>()
+            .add_systems(Update, update_vision_stamps.before(update_chunk_visibility));
+    }
+}
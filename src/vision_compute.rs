@@ -1,15 +1,25 @@
-use crate::prelude::VisionProvider;
+use crate::environment::VisionEnvironment;
+use crate::fog::{FogMaterial, FogOfWarConfig};
+use crate::height::VisionHeightMap;
+use crate::layer::{FogLayer, InFogLayer};
+use crate::mask::VisibilityReadback;
+use crate::prelude::{FogChunkManager, TemporaryReveal, VisionProvider};
+use crate::stamp::{VisionStamps, provider_rotation};
+use crate::vision_layers::VisionLayers;
 use crate::{VISIBILITY_TEXTURE_FORMAT, VISIBILITY_TEXTURE_SIZE};
 use bevy::ecs::query::QueryItem;
 use bevy::ecs::system::lifetimeless::Read;
 use bevy::render::render_graph::{RenderLabel, ViewNode};
 use bevy::render::render_resource::binding_types::{
-    storage_buffer, storage_buffer_read_only, texture_storage_2d, uniform_buffer,
+    sampler, storage_buffer, storage_buffer_read_only, texture_2d, texture_2d_array,
+    texture_storage_2d, uniform_buffer,
 };
+use bevy::render::render_asset::RenderAssets;
 use bevy::render::renderer::RenderDevice;
-use bevy::render::texture::CachedTexture;
+use bevy::render::texture::{CachedTexture, FallbackImage, GpuImage};
 use bevy::render::view::{ViewUniform, ViewUniformOffset, ViewUniforms};
-use bevy::render::{Extract, RenderApp};
+use bevy::render::{Extract, Render, RenderApp, RenderSet};
+use bevy::utils::HashMap;
 use bevy::{
     prelude::*,
     render::{
@@ -25,16 +35,52 @@ use bytemuck::Zeroable;
 #[derive(Debug, Clone, Copy, ShaderType, Pod, Zeroable)]
 #[repr(C)]
 pub struct GpuVisionParams {
+    // 预乘的线性色调，放在首位以满足 vec4 的 16 字节对齐
+    tint: Vec4,
     position: Vec2,
     range: f32,
     falloff: f32,
+    // 绕 Z 轴的旋转（弧度）
+    rotation: f32,
+    // 印章图集层，-1 表示圆形视野
+    stamp_layer: i32,
+    // 可见性强度，临时揭示淡出时小于 1
+    strength: f32,
+    // 视点高度，看不到更高的地形
+    eye_height: f32,
+}
+
+// 迷雾空间变换在 GPU 中的表示
+#[derive(Debug, Clone, Copy, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+pub struct GpuFogSpace {
+    world_to_fog: Mat2,
+    // 高度图左下角在迷雾空间中的位置
+    height_origin: Vec2,
+    // 高度图单元格边长
+    height_cell_size: f32,
+    // 是否使用高度图
+    use_height_map: u32,
 }
 
 // 视野参数资源
 #[derive(Resource, Default)]
 pub struct VisionParamsResource {
+    // 每个迷雾层的视野参数，`None` 为默认层
+    pub layers: HashMap<Option<Entity>, LayerVisionParams>,
+    // 视野印章图集
+    pub stamp_atlas: Option<AssetId<Image>>,
+    // 地形高度纹理
+    pub height_map: Option<AssetId<Image>>,
+}
+
+// 单个迷雾层的视野参数
+#[derive(Default)]
+pub struct LayerVisionParams {
     pub params: Vec<GpuVisionParams>,
     pub buffer: Option<Buffer>,
+    // 世界到迷雾空间的变换
+    pub fog_space_buffer: Option<Buffer>,
 }
 
 // 计算管线
@@ -76,7 +122,15 @@ impl FromWorld for VisionComputePipeline {
                     // Vision params storage buffer (group 1, binding 0)
                     storage_buffer_read_only::<GpuVisionParams>(false),
                     // Output texture (group 1, binding 1)
-                    texture_storage_2d(TextureFormat::R32Float, StorageTextureAccess::WriteOnly),
+                    texture_storage_2d(VISIBILITY_TEXTURE_FORMAT, StorageTextureAccess::WriteOnly),
+                    // Vision stamp atlas (group 1, binding 2)
+                    texture_2d_array(TextureSampleType::Float { filterable: true }),
+                    // Vision stamp sampler (group 1, binding 3)
+                    sampler(SamplerBindingType::Filtering),
+                    // World to fog space transform (group 1, binding 4)
+                    uniform_buffer::<GpuFogSpace>(false),
+                    // Terrain height map (group 1, binding 5)
+                    texture_2d(TextureSampleType::Float { filterable: false }),
                 ),
             ),
         );
@@ -105,7 +159,9 @@ impl FromWorld for VisionComputePipeline {
             sample_count: 1,
             dimension: TextureDimension::D2,
             format: VISIBILITY_TEXTURE_FORMAT,
-            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            usage: TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
             view_formats: &[],
         };
 
@@ -118,33 +174,125 @@ impl FromWorld for VisionComputePipeline {
     }
 }
 
-// 更新视野参数的 system
+type FogLayerQuery<'w, 's> =
+    Query<'w, 's, (Entity, &'static FogOfWarConfig, &'static FogChunkManager), With<FogLayer>>;
+
+type VisionProviderQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static GlobalTransform,
+        &'static VisionProvider,
+        Option<&'static TemporaryReveal>,
+        Option<&'static InFogLayer>,
+    ),
+>;
+
+// 更新视野参数的 system，默认层和每个迷雾层各有一组参数
+#[allow(clippy::too_many_arguments)]
 pub fn update_vision_params(
     mut vision_params: ResMut<VisionParamsResource>,
     render_device: Res<RenderDevice>,
-    query: Extract<Query<(&GlobalTransform, &VisionProvider)>>,
+    config: Extract<Res<FogOfWarConfig>>,
+    environment: Extract<Res<VisionEnvironment>>,
+    stamps: Extract<Res<VisionStamps>>,
+    heights: Extract<Res<VisionHeightMap>>,
+    chunk_manager: Extract<Res<FogChunkManager>>,
+    layers: Extract<FogLayerQuery>,
+    query: Extract<VisionProviderQuery>,
 ) {
-    let params: Vec<GpuVisionParams> = query
-        .iter()
-        .map(|(transform, provider)| GpuVisionParams {
-            position: transform.translation().truncate(),
-            range: provider.range,
-            falloff: 0.5,
-        })
-        .collect();
-
-    vision_params.params = params;
-
-    // 更新或创建缓冲区
-    if vision_params.params.is_empty() {
-        vision_params.buffer = None;
-    } else {
-        let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
-            label: Some("vision_params_buffer"),
-            contents: bytemuck::cast_slice(&vision_params.params),
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
-        });
-        vision_params.buffer = Some(buffer);
+    vision_params.stamp_atlas = stamps.atlas.as_ref().map(Handle::id);
+    vision_params.height_map = heights.image().map(Handle::id);
+
+    // 移除已销毁的迷雾层
+    // Remove despawned fog layers
+    vision_params
+        .layers
+        .retain(|layer, _| layer.is_none_or(|layer| layers.contains(layer)));
+
+    let fog_layers = core::iter::once((None, &**config, &**chunk_manager)).chain(
+        layers
+            .iter()
+            .map(|(layer, config, chunk_manager)| (Some(layer), config, chunk_manager)),
+    );
+    for (layer, config, chunk_manager) in fog_layers {
+        let layer_params = vision_params.layers.entry(layer).or_default();
+        layer_params.fog_space_buffer = Some(render_device.create_buffer_with_data(
+            &BufferInitDescriptor {
+                label: Some("fog_space_buffer"),
+                contents: bytemuck::bytes_of(&GpuFogSpace {
+                    world_to_fog: config.world_to_fog,
+                    height_origin: heights.origin,
+                    height_cell_size: heights.cell_size,
+                    use_height_map: heights.image().is_some() as u32,
+                }),
+                usage: BufferUsages::UNIFORM,
+            },
+        ));
+
+        // 冻结时保留上一次的视野参数
+        // Keep the last vision params while the fog is frozen
+        if chunk_manager.is_frozen() && layer_params.buffer.is_some() {
+            continue;
+        }
+
+        layer_params.params = query
+            .iter()
+            .filter(|(.., in_layer)| in_layer.map(|in_layer| in_layer.0) == layer)
+            .filter_map(|(transform, provider, reveal, _)| {
+                // 只有能看到迷雾显示的视野层的提供者会驱散迷雾
+                // Only providers seeing the vision layers the fog shows clear the fog
+                let layers = provider.layers & config.vision_layers;
+                if layers == VisionLayers::NONE {
+                    return None;
+                }
+                let position = transform.translation().truncate();
+                let fog_position = config.to_fog(position);
+                let tint = provider.tint.to_linear();
+                Some(GpuVisionParams {
+                    tint: Vec4::new(
+                        tint.red * tint.alpha,
+                        tint.green * tint.alpha,
+                        tint.blue * tint.alpha,
+                        tint.alpha,
+                    ),
+                    position: fog_position,
+                    range: environment.range_for(provider.range, position, layers),
+                    falloff: 0.5,
+                    rotation: provider_rotation(transform),
+                    stamp_layer: provider
+                        .stamp
+                        .as_ref()
+                        .and_then(|stamp| stamps.layer(stamp))
+                        .map_or(-1, |layer| layer as i32),
+                    strength: reveal.map_or(1.0, TemporaryReveal::strength),
+                    // 地形不遮挡提供者所在的层或这些层时视点高于任何台地
+                    // The eye is above every ledge when the terrain does not occlude the provider's layer or these layers
+                    eye_height: if heights.occludes(provider.lives_on | layers) {
+                        heights.height_at(fog_position) + provider.elevation
+                    } else {
+                        f32::MAX
+                    },
+                })
+            })
+            .collect();
+
+        // 没有视野提供者时上传一个强度为 0 的参数，使计算着色器清空可见性纹理
+        // Upload a zero-strength param when there are no providers, so the compute shader clears the visibility texture
+        if layer_params.params.is_empty() {
+            layer_params.params.push(GpuVisionParams {
+                stamp_layer: -1,
+                ..GpuVisionParams::zeroed()
+            });
+        }
+
+        layer_params.buffer = Some(render_device.create_buffer_with_data(
+            &BufferInitDescriptor {
+                label: Some("vision_params_buffer"),
+                contents: bytemuck::cast_slice(&layer_params.params),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            },
+        ));
     }
 }
 
@@ -155,6 +303,24 @@ pub struct VisibilityTextureResource {
     pub texture: Option<CachedTexture>,
 }
 
+// 创建可见性纹理的 system，需在绑定组准备之前运行
+pub fn prepare_visibility_texture(
+    pipeline: Res<VisionComputePipeline>,
+    render_device: Res<RenderDevice>,
+    mut visibility_resource: ResMut<VisibilityTextureResource>,
+) {
+    if visibility_resource.texture.is_some() {
+        return;
+    }
+
+    let texture = render_device.create_texture(&pipeline.texture_descriptor);
+    let default_view = texture.create_view(&TextureViewDescriptor::default());
+    visibility_resource.texture = Some(CachedTexture {
+        texture,
+        default_view,
+    });
+}
+
 // 视野计算插件
 pub struct VisionComputePlugin;
 
@@ -166,7 +332,11 @@ impl Plugin for VisionComputePlugin {
         render_app
             .init_resource::<VisionParamsResource>()
             .init_resource::<VisibilityTextureResource>()
-            .add_systems(ExtractSchedule, update_vision_params);
+            .add_systems(ExtractSchedule, update_vision_params)
+            .add_systems(
+                Render,
+                prepare_visibility_texture.in_set(RenderSet::PrepareResources),
+            );
     }
 }
 
@@ -175,33 +345,23 @@ pub struct VisionComputeLabel;
 
 // 计算节点
 pub struct VisionComputeNode {
-    pub visibility_texture: Option<CachedTexture>,
-    vision_params_buffer: Option<Buffer>,
     result_buffer: Option<Buffer>,
 }
 
 impl Default for VisionComputeNode {
     fn default() -> Self {
         Self {
-            visibility_texture: None,
-            vision_params_buffer: None,
             result_buffer: None,
         }
     }
 }
 
 impl ViewNode for VisionComputeNode {
-    type ViewQuery = (Read<ViewUniformOffset>);
+    type ViewQuery = (Read<ViewUniformOffset>, Option<Read<FogMaterial>>);
 
     fn update(&mut self, world: &mut World) {
-        // 首先获取所有需要的资源
-        let pipeline = world.resource::<VisionComputePipeline>();
-        let vision_params = world.resource::<VisionParamsResource>();
         let render_device = world.resource::<RenderDevice>();
 
-        // 使用已经准备好的缓冲区
-        self.vision_params_buffer = vision_params.buffer.clone();
-
         // 创建结果缓冲区
         if self.result_buffer.is_none() {
             self.result_buffer = Some(render_device.create_buffer(&BufferDescriptor {
@@ -211,30 +371,13 @@ impl ViewNode for VisionComputeNode {
                 mapped_at_creation: false,
             }));
         }
-
-        // 确保可见性纹理存在
-        if self.visibility_texture.is_none() {
-            let texture = render_device.create_texture(&pipeline.texture_descriptor);
-            let default_view = texture.create_view(&TextureViewDescriptor::default());
-            self.visibility_texture = Some(CachedTexture {
-                texture,
-                default_view,
-            });
-        }
-
-        // 更新可见性纹理资源
-        // Update visibility texture resource
-        if let Some(visibility_texture) = &self.visibility_texture {
-            let mut visibility_resource = world.resource_mut::<VisibilityTextureResource>();
-            visibility_resource.texture = Some(visibility_texture.clone());
-        }
     }
 
     fn run(
         &self,
         _graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
-        (view_uniform_offset): QueryItem<Self::ViewQuery>,
+        (view_uniform_offset, fog_material): QueryItem<Self::ViewQuery>,
         world: &World,
     ) -> Result<(), NodeRunError> {
         let pipeline = world.resource::<VisionComputePipeline>();
@@ -250,11 +393,37 @@ impl ViewNode for VisionComputeNode {
             return Ok(());
         };
 
-        let Some(vision_params_buffer) = &self.vision_params_buffer else {
+        // 使用视图所选迷雾层的视野参数
+        // Use the vision params of the view's fog layer
+        let vision_params = world.resource::<VisionParamsResource>();
+        let Some(layer_params) = vision_params
+            .layers
+            .get(&fog_material.and_then(|fog_material| fog_material.layer))
+        else {
+            return Ok(());
+        };
+        let (Some(vision_params_buffer), Some(fog_space_buffer)) =
+            (&layer_params.buffer, &layer_params.fog_space_buffer)
+        else {
             return Ok(());
         };
 
-        let visibility_texture = self.visibility_texture.as_ref().unwrap();
+        let Some(visibility_texture) = &world.resource::<VisibilityTextureResource>().texture
+        else {
+            return Ok(());
+        };
+
+        // 获取视野印章图集，未就绪时使用回退图像
+        // Get the vision stamp atlas, using the fallback image while it is not ready
+        let fallback_image = world.resource::<FallbackImage>();
+        let stamp_atlas = vision_params
+            .stamp_atlas
+            .and_then(|id| world.resource::<RenderAssets<GpuImage>>().get(id))
+            .unwrap_or(&fallback_image.d2_array);
+        let height_map = vision_params
+            .height_map
+            .and_then(|id| world.resource::<RenderAssets<GpuImage>>().get(id))
+            .unwrap_or(&fallback_image.d2);
 
         // 创建视图绑定组 (group 0)
         let view_bind_group = render_context.render_device().create_bind_group(
@@ -272,6 +441,10 @@ impl ViewNode for VisionComputeNode {
             &BindGroupEntries::sequential((
                 vision_params_buffer.as_entire_binding(),
                 &visibility_texture.default_view,
+                &stamp_atlas.texture_view,
+                &stamp_atlas.sampler,
+                fog_space_buffer.as_entire_binding(),
+                &height_map.texture_view,
             )),
         );
 
@@ -286,6 +459,13 @@ impl ViewNode for VisionComputeNode {
         let workgroup_size = 8;
         let dispatch_size = (VISIBILITY_TEXTURE_SIZE + workgroup_size - 1) / workgroup_size;
         compute_pass.dispatch_workgroups(dispatch_size, dispatch_size, 1);
+        drop(compute_pass);
+
+        // 如果请求了快照，复制可见性纹理用于读回
+        // Copy the visibility texture for readback if a snapshot was requested
+        world
+            .resource::<VisibilityReadback>()
+            .copy_visibility_texture(render_context, world);
 
         Ok(())
     }
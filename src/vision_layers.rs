@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use core::ops::{BitAnd, BitOr, BitOrAssign};
+
+/// 视野层，例如地面、空中和水下
+/// Vision layers, e.g. ground, air and underwater
+///
+/// 视野提供者通过 [`VisionProvider::layers`](crate::prelude::VisionProvider::layers) 声明能看到的层，
+/// 视野区域通过 [`VisionZone::layers`](crate::prelude::VisionZone::layers) 声明影响的层，
+/// 地形通过 [`VisionHeightMap::layers`](crate::prelude::VisionHeightMap::layers) 声明遮挡的层；
+/// 作为组件添加到 [`FogTarget`](crate::prelude::FogTarget) 上时表示目标所在的层，默认为地面。
+/// 迷雾只显示 [`FogOfWarConfig::vision_layers`](crate::prelude::FogOfWarConfig::vision_layers) 上的视野，
+/// 因此只能看到水下的声呐不会驱散地面迷雾，但仍能发现水下目标
+/// Vision providers declare the layers they see with [`VisionProvider::layers`](crate::prelude::VisionProvider::layers),
+/// vision zones the layers they affect with [`VisionZone::layers`](crate::prelude::VisionZone::layers),
+/// and the terrain the layers it occludes with [`VisionHeightMap::layers`](crate::prelude::VisionHeightMap::layers);
+/// as a component on a [`FogTarget`](crate::prelude::FogTarget) it is the layer the target lives on, ground by default.
+/// The fog only shows sight on [`FogOfWarConfig::vision_layers`](crate::prelude::FogOfWarConfig::vision_layers),
+/// so a sonar that only sees underwater does not clear ground fog but still spots underwater targets
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub struct VisionLayers(pub u32);
+
+impl VisionLayers {
+    /// 不包含任何层 (No layer)
+    pub const NONE: Self = Self(0);
+    /// 地面层 (Ground layer)
+    pub const GROUND: Self = Self(1 << 0);
+    /// 空中层 (Air layer)
+    pub const AIR: Self = Self(1 << 1);
+    /// 水下层 (Underwater layer)
+    pub const UNDERWATER: Self = Self(1 << 2);
+    /// 所有层 (Every layer)
+    pub const ALL: Self = Self(u32::MAX);
+
+    /// 是否包含另一组层中的所有层
+    /// Whether every layer of another set is included
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// 是否与另一组层有共同的层
+    /// Whether any layer is shared with another set
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for VisionLayers {
+    fn default() -> Self {
+        Self::GROUND
+    }
+}
+
+impl BitOr for VisionLayers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for VisionLayers {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitOrAssign for VisionLayers {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}